@@ -1,33 +1,23 @@
-local function string_split(inputstr, sep)
-    if sep == nil then
-        sep = "%s"
-    end
-    local t = {}
-    for str in string.gmatch(inputstr, "(.-)(" .. sep .. ")") do
-        table.insert(t, str)
-    end
-    return t
-end
+local store = tudo.password_store or tudo.env("PASSWORD_STORE_DIR") or (tudo.env("HOME") .. "/.password-store")
 
-local function get_dir_contents(dir)
-    local i, t, popen = 0, {}, io.popen
+local res = {}
 
-    local pfile = popen("ls -1 '" .. dir .. "'")
-    if pfile == nil then
-        return {}
+-- Secrets in subfolders are named after their path, e.g. github.com/foo
+local function walk(dir, prefix)
+    for _, file in ipairs(tudo.read_dir(dir)) do
+        local title = file:match("^(.*)%.gpg$")
+        if title then
+            table.insert(res, {
+                title = prefix .. title,
+                action = { type = "secret", secret_name = prefix .. title, store = store },
+            })
+        elseif not file:match("^%.") then
+            -- Not a folder if it can't be listed
+            pcall(walk, dir .. "/" .. file, prefix .. file .. "/")
+        end
     end
-    for filename in pfile:lines() do
-        i = i + 1
-        t[i] = filename
-    end
-    pfile:close()
-    return t
 end
 
-local res = {}
-for i, file in ipairs(get_dir_contents(os.getenv("HOME") .. "/.password-store")) do
-    local title = string_split(file, ".gpg")[1]
-    res[i] = { title = title, icon = nil, action = { type = "secret", secret_name = title } }
-end
+walk(store, "")
 
 return res
@@ -9,6 +9,9 @@ use sdl2::VideoSubsystem;
 
 use crate::config::load_config;
 use crate::config::Config;
+use crate::history::{self, History};
+use crate::keymap::{Command, Keymap};
+use crate::sources::{actions::Action, SourceItem};
 use crate::utils::hyprland::Hyprland;
 
 pub struct App {
@@ -17,18 +20,35 @@ pub struct App {
     pub event_pump: EventPump,
 
     pub clipboard: Option<String>,
+    pub prompt: Option<String>,
     pub running: bool,
     pub draw_fps: bool,
     pub frame_lock: bool,
     pub loading: bool,
     pub current_screen_id: String,
     pub config: Config,
+    pub keymap: Keymap,
     pub layout_debug: bool,
     pub ctrl_pressed: bool,
     pub hyprland: std::io::Result<Hyprland>,
+    pub history: History,
 
     pub should_hide: bool,
     pub hidden: bool,
+    // Escape closes the actions menu instead of hiding the window
+    pub action_menu_open: bool,
+    // Items an action wants listed instead of the source items, e.g. from a Lua plugin
+    pub pushed_items: Option<Vec<SourceItem>>,
+    // Picked while its plugin was busy, e.g. answering a query. Tried again every frame
+    pub waiting_action: Option<Box<dyn Action + Send>>,
+    // What dynamic sources are asked about, the prompt text without the tag
+    pub query: String,
+    // Set by the reload key, the main loop re-applies config.lua
+    pub reload_requested: bool,
+
+    // One-shot dmenu mode, the process exits with `exit_code` instead of hiding
+    pub dmenu: bool,
+    pub exit_code: i32,
 }
 
 fn check_config_folder() -> String {
@@ -64,16 +84,19 @@ impl App {
         info!("Finished initializing canvas");
 
         let hyprland = Hyprland::new();
+        let history = History::load(history::default_path());
 
         (
             App {
                 sdl,
                 clipboard: None,
+                prompt: None,
                 video,
                 event_pump,
 
                 running: true,
                 frame_lock: config.frame_lock,
+                keymap: Keymap::from_config(&config.keys),
                 draw_fps: false,
                 loading: true,
                 current_screen_id: "main".to_string(),
@@ -81,9 +104,18 @@ impl App {
                 layout_debug: false,
                 ctrl_pressed: false,
                 hyprland,
+                history,
 
                 should_hide: false,
                 hidden: false,
+                action_menu_open: false,
+                pushed_items: None,
+                waiting_action: None,
+                query: String::new(),
+                reload_requested: false,
+
+                dmenu: false,
+                exit_code: 0,
             },
             canvas,
         )
@@ -105,37 +137,23 @@ impl App {
                     keycode: Some(Keycode::LCtrl),
                     ..
                 } => self.ctrl_pressed = false,
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::F1),
-                    ..
-                } => self.draw_fps = !self.draw_fps,
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::F2),
-                    ..
-                } => self.frame_lock = !self.frame_lock,
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
+                sdl2::event::Event::Quit { .. } => self.running = false,
+                _ => (),
+            }
+
+            match self.keymap.lookup(event) {
+                Some(Command::ToggleFps) => self.draw_fps = !self.draw_fps,
+                Some(Command::ToggleFrameLock) => self.frame_lock = !self.frame_lock,
+                Some(Command::ToggleLayoutDebug) => self.layout_debug ^= true,
+                Some(Command::OpenScreen(screen)) => self.current_screen_id = screen,
+                Some(Command::Reload) => self.reload_requested = true,
+                Some(Command::Back) => {
                     if &self.current_screen_id == "main" {
-                        self.should_hide = true;
+                        self.should_hide = !self.action_menu_open;
                     } else {
                         self.current_screen_id = "main".to_string()
                     }
                 }
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::F3),
-                    ..
-                } => self.current_screen_id = "debug".to_string(),
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::F4),
-                    ..
-                } => self.current_screen_id = "info".to_string(),
-                sdl2::event::Event::KeyDown {
-                    keycode: Some(Keycode::F12),
-                    ..
-                } => self.layout_debug ^= true,
-                sdl2::event::Event::Quit { .. } => self.running = false,
                 _ => (),
             }
         }
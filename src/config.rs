@@ -1,88 +1,148 @@
 use std::collections::HashMap;
 
-use mlua::{Lua, LuaSerdeExt};
-use sdl2::pixels::Color;
+use log::warn;
+use mlua::{Lua, LuaSerdeExt, Table, Value};
 
-use serde::{
-    de::{Error, Visitor},
-    ser::SerializeMap,
-    Deserialize, Deserializer, Serialize, Serializer,
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ui::theme::Theme,
+    utils::font::{FontConfig, DEFAULT_POINT_SIZE},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub font_family: String,
+    // Families glyphs missing from a font are taken from, in order
+    pub font_fallbacks: Vec<String>,
+    // Font aliases, including the theme ones, e.g. tudo.fonts.prompt = { size = 26, style = "Bold" }
+    pub fonts: HashMap<String, FontSpec>,
     pub cursor_blink: bool,
-    #[serde(serialize_with = "serialize_color")]
-    #[serde(deserialize_with = "deserialize_color")]
-    pub prompt_color: Color,
+    pub theme: Theme,
     pub fast_start: bool,
-    pub pid_file: String,
     pub frame_lock: bool,
+    // In the order their items are listed, ties in ranking keep it
+    pub sources: Vec<SourceConfig>,
+    // Every script or plugin folder in here is loaded as a source after `sources`, "" turns it off
+    pub plugins_dir: String,
+    // Key chord to command overrides, e.g. tudo.keys["ctrl+j"] = "select-next"
+    pub keys: HashMap<String, String>,
 }
 
-fn serialize_color<S>(color: &Color, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let mut map = serializer.serialize_map(Some(4))?;
-    map.serialize_entry("r", &color.r)?;
-    map.serialize_entry("g", &color.g)?;
-    map.serialize_entry("b", &color.r)?;
-    map.serialize_entry("a", &color.a)?;
-    map.end()
+// Unset fields keep the theme size and the main family
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontSpec {
+    pub family: Option<String>,
+    pub size: Option<u16>,
+    pub style: Option<String>,
 }
 
-fn deserialize_color<'de, D>(des: D) -> Result<Color, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct ColorVisitor;
-    impl<'de> Visitor<'de> for ColorVisitor {
-        type Value = Color;
-
-        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-        where
-            A: serde::de::MapAccess<'de>,
-        {
-            let mut r: u8 = 0;
-            let mut g: u8 = 0;
-            let mut b: u8 = 0;
-            let mut a: u8 = 0;
-            while let Some((key, value)) = map.next_entry::<String, u8>().unwrap() {
-                match key.as_str() {
-                    "r" => r = value,
-                    "g" => g = value,
-                    "b" => b = value,
-                    "a" => a = value,
-                    _ => return Err(Error::custom("Unknown key")),
-                }
-            }
-            Ok(Color::RGBA(r, g, b, a))
-        }
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("Expects a map with 4 keys 'r', 'g', 'b', 'a'")
+// Built-in sources and their options, selected by `type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SourceKind {
+    Apps,
+    Windows,
+    Secrets {
+        // Defaults to $PASSWORD_STORE_DIR, then ~/.password-store
+        store: Option<String>,
+    },
+    Tmux {
+        // Command the session is attached in, e.g. "kitty -e"
+        terminal: Option<String>,
+    },
+    Lua {
+        path: String,
+    },
+}
+
+// An entry of `tudo.sources`, e.g. { type = "tmux", prefix = "t", terminal = "kitty -e" }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceConfig {
+    #[serde(flatten)]
+    pub kind: SourceKind,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Tag the items are searched by with ":prefix", instead of the action tag
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl SourceConfig {
+    pub fn new(kind: SourceKind) -> SourceConfig {
+        SourceConfig {
+            kind,
+            enabled: true,
+            prefix: None,
         }
     }
-
-    Ok(des.deserialize_map(ColorVisitor))?
 }
 
 impl Config {
     fn new() -> Config {
         Default::default()
     }
+
+    // The theme fonts with the `fonts` overrides applied, keyed by alias
+    pub fn font_configs(&self) -> HashMap<String, FontConfig> {
+        let mut configs: HashMap<String, FontConfig> = self
+            .theme
+            .fonts()
+            .into_iter()
+            .map(|(alias, size)| {
+                (
+                    alias.to_string(),
+                    FontConfig::new(alias, &self.font_family, size),
+                )
+            })
+            .collect();
+
+        for (alias, spec) in self.fonts.iter() {
+            let config = configs
+                .entry(alias.clone())
+                .or_insert_with(|| FontConfig::new(alias, &self.font_family, DEFAULT_POINT_SIZE));
+            if let Some(family) = &spec.family {
+                config.family = family.clone();
+            }
+            if let Some(size) = spec.size {
+                config.point_size = size;
+            }
+            if let Some(style) = &spec.style {
+                config.style = style.clone();
+            }
+        }
+        configs
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             font_family: "JetBrainsMono Nerd Font".to_string(),
+            font_fallbacks: vec![
+                "Symbols Nerd Font".to_string(),
+                "Noto Sans CJK JP".to_string(),
+                "Noto Color Emoji".to_string(),
+                "DejaVu Sans".to_string(),
+            ],
+            fonts: HashMap::new(),
             cursor_blink: true,
-            prompt_color: Color::RGBA(255, 255, 255, 255),
+            theme: Theme::default(),
             fast_start: true,
-            pid_file: String::from("/run/user/1000/todo.pid"),
             frame_lock: false,
+            sources: vec![
+                SourceConfig::new(SourceKind::Apps),
+                SourceConfig::new(SourceKind::Windows),
+                SourceConfig::new(SourceKind::Secrets { store: None }),
+                SourceConfig::new(SourceKind::Tmux { terminal: None }),
+            ],
+            plugins_dir: "plugins".to_string(),
+            keys: HashMap::new(),
         }
     }
 }
@@ -92,6 +152,9 @@ pub fn set_globals(lua: &Lua) {
     let config = Config::new();
 
     globals.set("tudo", lua.to_value(&config).unwrap()).unwrap();
+    globals
+        .set("themes", lua.to_value(&Theme::builtin()).unwrap())
+        .unwrap();
     let color_func = lua.create_function(|_ctx, (r, g, b, a): (u8, u8, u8, u8)| {
         let mut hm: HashMap<String, u8> = HashMap::new();
         hm.insert("r".to_string(), r);
@@ -105,16 +168,55 @@ pub fn set_globals(lua: &Lua) {
     globals.set("color", color_func.unwrap()).unwrap();
 }
 
-pub fn load_config(path: impl AsRef<str>) -> Config {
+// Broken config files are reported instead of applied, so a reload keeps the running config
+pub fn try_load_config(path: impl AsRef<str>) -> Result<Config, mlua::Error> {
     let lua = Lua::new();
     let contents = std::fs::read(path.as_ref()).unwrap_or_else(|_| "".into());
 
-    let globals = lua.globals();
-
     set_globals(&lua);
 
+    lua.load(&contents).set_name("config").eval::<()>()?;
+    let tudo: Table = lua.globals().get("tudo")?;
+
+    // From before the theme, the prompt text used what is now the foreground colour
+    let prompt_color: Value = tudo.get("prompt_color")?;
+    if !prompt_color.is_nil() {
+        warn!("tudo.prompt_color is deprecated, use tudo.theme.foreground instead");
+        tudo.get::<_, Table>("theme")?
+            .set("foreground", prompt_color)?;
+    }
+
+    lua.from_value(Value::Table(tudo))
+}
+
+pub fn load_config(path: impl AsRef<str>) -> Config {
     // This currently panics if config.lua file is wrong in any way.
     // FIXME(quadrado): Deal better with this error
-    lua.load(&contents).set_name("config").eval::<()>().unwrap();
-    lua.from_value(globals.get("tudo").unwrap()).unwrap()
+    try_load_config(path).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_load_config() {
+        let path = std::env::temp_dir().join(format!("tudo-config-{}.lua", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        std::fs::write(&path, "tudo.font_family = 'Iosevka'").unwrap();
+        assert_eq!(try_load_config(&path).unwrap().font_family, "Iosevka");
+
+        // A half-written file while editing shouldn't take tudo down
+        std::fs::write(&path, "tudo.font_family = ").unwrap();
+        assert!(try_load_config(&path).is_err());
+
+        std::fs::write(&path, "tudo.prompt_color = color(255, 0, 0, 255)").unwrap();
+        assert_eq!(
+            try_load_config(&path).unwrap().theme.foreground,
+            sdl2::pixels::Color::RGBA(255, 0, 0, 255)
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,5 +1,9 @@
 use crate::{sources::SourceItem, App};
 
 pub fn execute(item: &SourceItem, ctx: &mut App) {
-    item.action.execute(ctx);
+    // dmenu items are throwaway, don't let them pollute the history
+    if !ctx.dmenu {
+        ctx.history.record(&item.id);
+    }
+    item.action().execute(ctx);
 }
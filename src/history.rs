@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use ureq::serde_json;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Entries not used for this long are dropped
+const MAX_AGE: u64 = 90 * DAY;
+// Upper bound on the number of entries kept, the least frecent ones go first
+const MAX_ENTRIES: usize = 1000;
+// Entries move to lower frecency buckets as they age, a long running instance rescores this often
+const RESCORE_AFTER: u64 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub count: u32,
+    pub last_used: u64,
+}
+
+impl HistoryEntry {
+    // Frequency weighted by how recently the item was used, Firefox style buckets
+    pub fn frecency(&self, now: u64) -> u32 {
+        let age = now.saturating_sub(self.last_used);
+        let weight = match age {
+            a if a < 4 * DAY => 100,
+            a if a < 14 * DAY => 70,
+            a if a < 31 * DAY => 50,
+            a if a < MAX_AGE => 30,
+            _ => 10,
+        };
+        self.count * weight
+    }
+}
+
+// Executed items, persisted across sessions and used to rank results
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<String>,
+    pub entries: HashMap<String, HistoryEntry>,
+    // Frecency of every entry, only recomputed when the entries change
+    scores: Arc<HashMap<String, u32>>,
+    scored_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn default_path() -> String {
+    let data_home = std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| {
+        let home = std::env::var("HOME").expect("$HOME not set, can't find history file");
+        format!("{}/.local/share", home)
+    });
+
+    format!("{}/tudo/history.json", data_home)
+}
+
+impl History {
+    pub fn load(path: impl AsRef<str>) -> History {
+        let entries = match std::fs::read(path.as_ref()) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring unreadable history {}: {}", path.as_ref(), err);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        let mut history = History {
+            path: Some(path.as_ref().to_string()),
+            entries,
+            scores: Arc::default(),
+            scored_at: 0,
+        };
+        history.prune(now());
+        history
+    }
+
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(err) = std::fs::write(path, serde_json::to_vec(&self.entries).unwrap()) {
+            warn!("Could not save history to {}: {}", path, err);
+        }
+    }
+
+    pub fn record(&mut self, key: impl AsRef<str>) {
+        let now = now();
+        let entry = self
+            .entries
+            .entry(key.as_ref().to_string())
+            .or_insert(HistoryEntry {
+                count: 0,
+                last_used: now,
+            });
+        entry.count += 1;
+        entry.last_used = now;
+
+        self.prune(now);
+        self.save();
+    }
+
+    // Cheap to call every frame, the map is shared and only rebuilt once it got old
+    pub fn scores(&mut self) -> Arc<HashMap<String, u32>> {
+        let now = now();
+        if now.saturating_sub(self.scored_at) >= RESCORE_AFTER {
+            self.prune(now);
+        }
+        self.scores.clone()
+    }
+
+    // Drop entries that are too old and cap the total number of entries
+    pub fn prune(&mut self, now: u64) {
+        self.entries
+            .retain(|_, entry| now.saturating_sub(entry.last_used) < MAX_AGE);
+
+        if self.entries.len() > MAX_ENTRIES {
+            let mut by_frecency = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (entry.frecency(now), entry.last_used, key.clone()))
+                .collect::<Vec<(u32, u64, String)>>();
+            by_frecency.sort();
+
+            let excess = self.entries.len() - MAX_ENTRIES;
+            for (_, _, key) in by_frecency.into_iter().take(excess) {
+                self.entries.remove(&key);
+            }
+        }
+
+        self.scores = Arc::new(
+            self.entries
+                .iter()
+                .map(|(key, entry)| (key.clone(), entry.frecency(now)))
+                .collect(),
+        );
+        self.scored_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frecency_prefers_recent() {
+        let now = 1000 * DAY;
+        let recent = HistoryEntry {
+            count: 3,
+            last_used: now - DAY,
+        };
+        let old = HistoryEntry {
+            count: 5,
+            last_used: now - 60 * DAY,
+        };
+        assert!(recent.frecency(now) > old.frecency(now));
+    }
+
+    #[test]
+    fn test_prune() {
+        let now = 1000 * DAY;
+        let mut history = History::default();
+        history.entries.insert(
+            "stale".to_string(),
+            HistoryEntry {
+                count: 100,
+                last_used: now - MAX_AGE - 1,
+            },
+        );
+        for i in 0..MAX_ENTRIES + 10 {
+            history.entries.insert(
+                format!("item-{}", i),
+                HistoryEntry {
+                    count: 1 + (i as u32 % 3),
+                    last_used: now,
+                },
+            );
+        }
+
+        let least_used = history.entries.values().filter(|e| e.count == 1).count();
+
+        history.prune(now);
+
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert!(!history.entries.contains_key("stale"));
+        // Least used entries are pruned first
+        assert_eq!(
+            history.entries.values().filter(|e| e.count == 1).count(),
+            least_used - 10
+        );
+    }
+
+    #[test]
+    fn test_scores_follow_record() {
+        let mut history = History::default();
+        history.record("apps:firefox");
+        assert_eq!(history.scores().get("apps:firefox"), Some(&100));
+
+        history.record("apps:firefox");
+        assert_eq!(history.scores().get("apps:firefox"), Some(&200));
+    }
+
+    #[test]
+    fn test_scores_age() {
+        let mut history = History::default();
+        history.entries.insert(
+            "apps:firefox".to_string(),
+            HistoryEntry {
+                count: 1,
+                last_used: now() - 5 * DAY,
+            },
+        );
+        // Scored two days ago, the entry was in the most recent bucket back then
+        history.prune(now() - 2 * DAY);
+        assert_eq!(history.scores.get("apps:firefox"), Some(&100));
+
+        assert_eq!(history.scores().get("apps:firefox"), Some(&70));
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use log::{info, warn};
+
+pub const USAGE: &str =
+    "Usage: tudo [show | hide | toggle | quit | screen <id> | prompt <text> | reload]";
+
+// Commands understood by a running instance. One command per line on the control socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Show,
+    Hide,
+    Toggle,
+    Quit,
+    Screen(String),
+    Prompt(String),
+    Reload,
+}
+
+impl Command {
+    pub fn parse(line: impl AsRef<str>) -> Result<Command, String> {
+        let line = line.as_ref().trim_end_matches(['\r', '\n']);
+        let (verb, arg) = line.split_once(' ').unwrap_or((line, ""));
+
+        match (verb, arg) {
+            ("show", "") => Ok(Command::Show),
+            ("hide", "") => Ok(Command::Hide),
+            ("toggle", "") => Ok(Command::Toggle),
+            ("quit", "") => Ok(Command::Quit),
+            ("reload", "") => Ok(Command::Reload),
+            ("screen", "") => Err("screen needs a screen id".to_string()),
+            ("screen", id) => Ok(Command::Screen(id.trim().to_string())),
+            ("prompt", text) => Ok(Command::Prompt(text.to_string())),
+            _ => Err(format!("Unknown command '{}'", line)),
+        }
+    }
+
+    // Command line arguments are joined and parsed as a single socket line.
+    // No arguments means "show", which keeps the old behaviour of a second invocation.
+    pub fn from_args(args: &[String]) -> Result<Command, String> {
+        if args.is_empty() {
+            return Ok(Command::Show);
+        }
+        Command::parse(args.join(" "))
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Show => write!(f, "show"),
+            Command::Hide => write!(f, "hide"),
+            Command::Toggle => write!(f, "toggle"),
+            Command::Quit => write!(f, "quit"),
+            Command::Reload => write!(f, "reload"),
+            Command::Screen(id) => write!(f, "screen {}", id),
+            Command::Prompt(text) => write!(f, "prompt {}", text),
+        }
+    }
+}
+
+pub fn socket_path() -> String {
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+        .unwrap_or_else(|_| format!("/run/user/{}", unsafe { sdl2::libc::getuid() }));
+
+    format!("{}/tudo.sock", runtime_dir)
+}
+
+// Forward a command to a running instance. Fails if nobody is listening on the socket.
+pub fn send(command: &Command) -> std::io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+
+    Ok(response.trim().to_string())
+}
+
+// Bind the control socket and forward every received command to `tx`.
+pub fn listen(tx: Sender<Command>) -> std::io::Result<()> {
+    let path = socket_path();
+
+    // A socket file nobody answers on is a leftover from an instance that didn't exit cleanly
+    if std::fs::exists(&path)? && UnixStream::connect(&path).is_err() {
+        warn!("Removing stale control socket {}", path);
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    info!("Listening for commands on {}", path);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let Ok(reader) = stream.try_clone().map(BufReader::new) else {
+                continue;
+            };
+
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                let response = match Command::parse(&line) {
+                    Ok(command) => {
+                        info!("Received command: {}", command);
+                        tx.send(command).unwrap();
+                        "ok".to_string()
+                    }
+                    Err(err) => format!("error: {}", err),
+                };
+
+                if stream
+                    .write_all(format!("{}\n", response).as_bytes())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub fn cleanup() {
+    let _ = std::fs::remove_file(socket_path());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("toggle"), Ok(Command::Toggle));
+        assert_eq!(
+            Command::parse("screen debug\n"),
+            Ok(Command::Screen("debug".to_string()))
+        );
+        assert_eq!(
+            Command::parse("prompt :window fire"),
+            Ok(Command::Prompt(":window fire".to_string()))
+        );
+        assert!(Command::parse("screen").is_err());
+        assert!(Command::parse("dance").is_err());
+    }
+
+    #[test]
+    fn test_command_roundtrip() {
+        for command in [
+            Command::Show,
+            Command::Quit,
+            Command::Reload,
+            Command::Screen("info".to_string()),
+            Command::Prompt("foo bar".to_string()),
+        ] {
+            assert_eq!(Command::parse(command.to_string()), Ok(command));
+        }
+    }
+
+    #[test]
+    fn test_from_args() {
+        assert_eq!(Command::from_args(&[]), Ok(Command::Show));
+        assert_eq!(
+            Command::from_args(&["prompt".to_string(), "foo".to_string()]),
+            Ok(Command::Prompt("foo".to_string()))
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use log::warn;
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+};
+
+// Everything a key can be bound to. Names are the ones used in config.lua, e.g. "select-next"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Command {
+    SelectNext,
+    SelectPrev,
+    Execute,
+    // Second action of the selected item, in dmenu mode print the typed text instead
+    ExecuteSecondary,
+    ActionsMenu,
+    // Close the actions menu, go back to the main screen or hide, whichever comes first
+    Back,
+    Calc,
+    CursorLeft,
+    CursorRight,
+    CursorHome,
+    CursorEnd,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    ClearLine,
+    ToggleFps,
+    ToggleFrameLock,
+    ToggleLayoutDebug,
+    // Re-read config.lua and the plugins
+    Reload,
+    OpenScreen(String),
+}
+
+const COMMANDS: [(&str, Command); 19] = [
+    ("select-next", Command::SelectNext),
+    ("select-prev", Command::SelectPrev),
+    ("execute", Command::Execute),
+    ("execute-secondary", Command::ExecuteSecondary),
+    ("actions-menu", Command::ActionsMenu),
+    ("back", Command::Back),
+    ("calc", Command::Calc),
+    ("cursor-left", Command::CursorLeft),
+    ("cursor-right", Command::CursorRight),
+    ("cursor-home", Command::CursorHome),
+    ("cursor-end", Command::CursorEnd),
+    ("delete-back", Command::DeleteBack),
+    ("delete-forward", Command::DeleteForward),
+    ("delete-word-back", Command::DeleteWordBack),
+    ("clear-line", Command::ClearLine),
+    ("toggle-fps", Command::ToggleFps),
+    ("toggle-frame-lock", Command::ToggleFrameLock),
+    ("toggle-layout-debug", Command::ToggleLayoutDebug),
+    ("reload", Command::Reload),
+];
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(screen) = s.strip_prefix("open-screen:") {
+            return Ok(Command::OpenScreen(screen.to_string()));
+        }
+        COMMANDS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, command)| command.clone())
+            .ok_or(format!("unknown command '{}'", s))
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::OpenScreen(screen) => write!(f, "open-screen:{}", screen),
+            _ => {
+                let (name, _) = COMMANDS.iter().find(|(_, c)| c == self).unwrap();
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+// A key plus the modifiers held with it, written like "ctrl+shift+p". Left and right
+// modifiers are the same, lock keys are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key: Keycode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Chord {
+    pub fn new(key: Keycode) -> Chord {
+        Chord {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+            logo: false,
+        }
+    }
+
+    pub fn from_event(event: &Event) -> Option<Chord> {
+        let Event::KeyDown {
+            keycode: Some(key),
+            keymod,
+            ..
+        } = event
+        else {
+            return None;
+        };
+
+        Some(Chord {
+            key: *key,
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+            logo: keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD),
+        })
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split("+").map(|part| part.trim()).collect();
+        let key = parts.pop().unwrap();
+        let mut chord =
+            Chord::new(Keycode::from_name(key).ok_or(format!("unknown key '{}'", key))?);
+
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                "super" | "logo" => chord.logo = true,
+                _ => return Err(format!("unknown modifier '{}'", modifier)),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+const DEFAULT_BINDINGS: [(&str, &str); 24] = [
+    ("down", "select-next"),
+    ("ctrl+n", "select-next"),
+    ("up", "select-prev"),
+    ("ctrl+p", "select-prev"),
+    ("return", "execute"),
+    ("shift+return", "execute-secondary"),
+    ("tab", "actions-menu"),
+    ("ctrl+return", "actions-menu"),
+    ("escape", "back"),
+    ("f5", "calc"),
+    ("left", "cursor-left"),
+    ("right", "cursor-right"),
+    ("home", "cursor-home"),
+    ("end", "cursor-end"),
+    ("backspace", "delete-back"),
+    ("delete", "delete-forward"),
+    ("ctrl+w", "delete-word-back"),
+    ("ctrl+u", "clear-line"),
+    ("f1", "toggle-fps"),
+    ("f2", "toggle-frame-lock"),
+    ("f12", "toggle-layout-debug"),
+    ("f3", "open-screen:debug"),
+    ("f4", "open-screen:info"),
+    ("ctrl+r", "reload"),
+];
+
+// Maps key chords to commands. Components ask it what a key press means instead of matching
+// keys themselves
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Chord, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        for (chord, command) in DEFAULT_BINDINGS {
+            keymap.bind(chord, command).unwrap();
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    // The defaults with `tudo.keys` on top. An empty command removes the default binding.
+    // Bad entries are skipped with a warning
+    pub fn from_config(keys: &HashMap<String, String>) -> Keymap {
+        let mut keymap = Keymap::default();
+        for (chord, command) in keys {
+            if let Err(err) = keymap.bind(chord, command) {
+                warn!("Ignoring key binding {} = {}: {}", chord, command, err);
+            }
+        }
+        keymap
+    }
+
+    pub fn bind(&mut self, chord: &str, command: &str) -> Result<(), String> {
+        let chord = chord.parse::<Chord>()?;
+        match command {
+            "" | "none" => {
+                self.bindings.remove(&chord);
+            }
+            _ => {
+                self.bindings.insert(chord, command.parse::<Command>()?);
+            }
+        }
+        Ok(())
+    }
+
+    // Command bound to a key press, None for anything else
+    pub fn lookup(&self, event: &Event) -> Option<Command> {
+        self.bindings.get(&Chord::from_event(event)?).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let chord = "Ctrl+Shift+p".parse::<Chord>().unwrap();
+        assert_eq!(chord.key, Keycode::P);
+        assert!(chord.ctrl && chord.shift && !chord.alt);
+        assert!("hyper+p".parse::<Chord>().is_err());
+
+        assert_eq!(
+            "open-screen:debug".parse::<Command>(),
+            Ok(Command::OpenScreen("debug".to_string()))
+        );
+        for (name, command) in COMMANDS {
+            assert_eq!(command.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let keys = HashMap::from([
+            ("ctrl+j".to_string(), "select-next".to_string()),
+            ("down".to_string(), "".to_string()),
+            ("ctrl+k".to_string(), "jump".to_string()),
+        ]);
+        let keymap = Keymap::from_config(&keys);
+
+        let ctrl_j = Chord {
+            ctrl: true,
+            ..Chord::new(Keycode::J)
+        };
+        assert_eq!(keymap.bindings.get(&ctrl_j), Some(&Command::SelectNext));
+        assert!(!keymap.bindings.contains_key(&Chord::new(Keycode::Down)));
+        assert_eq!(
+            keymap.bindings.len(),
+            Keymap::default().bindings.len(),
+            "one binding added, one removed and the invalid one skipped"
+        );
+    }
+}
@@ -2,15 +2,17 @@ pub mod animation;
 pub mod app;
 pub mod config;
 pub mod execute;
+pub mod history;
+pub mod ipc;
+pub mod keymap;
 pub mod screen;
 pub mod sources;
 pub mod utils;
 
 pub mod ui;
 
-use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::process::Command;
+use std::path::Path;
 use std::sync::mpsc::channel;
 use std::thread;
 use std::thread::sleep;
@@ -20,7 +22,8 @@ use std::time::Instant;
 use app::App;
 
 use execute::execute;
-use log::info;
+use ipc::Command;
+use log::{info, warn};
 use mlua::Lua;
 use screen::debug_screen::DebugScreen;
 use screen::info_screen::InfoScreen;
@@ -32,41 +35,183 @@ use sdl2::libc::SIGUSR2;
 use signal_hook::iterator::Signals;
 use simple_logger::SimpleLogger;
 use sources::Source;
+use sources::SourceEntry;
 
+use config::{try_load_config, Config, SourceKind};
+use keymap::Keymap;
 use screen::Screen;
 use sdl2::pixels::Color;
 use sources::apps::DesktopApplications;
+use sources::lua::LuaSource;
+use sources::manager::SourceManager;
+use sources::plugins;
 use sources::secrets::Secrets;
+use sources::stdin::Stdin;
 use sources::tmux::Tmux;
+use sources::watcher::Watcher;
 use sources::windows::WindowSource;
-use sources::SourceItem;
-use std::sync::{Arc, Mutex};
+use ui::theme::UI_FONT;
 use utils::cache::TextureCache;
+use utils::clipboard::set_clipboard;
 use utils::draw::draw_string;
-use utils::font::FontConfig;
 use utils::misc;
 
-const PID_FILE: &str = "/run/user/1000/tudo.pid"; //TODO(quadrado): Use configuration value instead
-                                                  //of this one.
-
-fn check_running_state() -> bool {
-    match std::fs::exists(PID_FILE) {
-        Ok(true) => {
-            let pid = String::from_utf8(std::fs::read(PID_FILE).unwrap()).unwrap();
-            println!("Lock file PID: {}", pid);
-            if !std::fs::exists(format!("/proc/{}", pid)).unwrap() {
-                println!("PID in lock file not running. Starting new instance");
-                return false;
+// Try to hand the command over to an already running instance
+fn check_running_state(command: &Command) -> bool {
+    match ipc::send(command) {
+        Ok(response) => {
+            println!("Sent '{}' to running tudo instance: {}", command, response);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// Paths in config.lua are relative to the config folder, "~/" is the home folder
+fn config_path(path: &str) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", home, rest),
+        None if path.starts_with("/") => path.to_string(),
+        None => format!("{}/.config/tudo/{}", home, path),
+    }
+}
+
+fn get_sources(dmenu: bool, config: &Config) -> Vec<SourceEntry> {
+    if dmenu {
+        return vec![SourceEntry {
+            source: Box::new(Stdin::new()),
+            prefix: None,
+        }];
+    }
+
+    // Lua scripts hand secrets to the same store the secrets source lists
+    let password_store = config.sources.iter().find_map(|entry| match &entry.kind {
+        SourceKind::Secrets { store: Some(store) } => Some(config_path(store)),
+        _ => None,
+    });
+
+    let mut sources: Vec<SourceEntry> = config
+        .sources
+        .iter()
+        .filter(|entry| entry.enabled)
+        .map(|entry| {
+            let source: Box<dyn Source + Send> = match &entry.kind {
+                SourceKind::Apps => Box::new(DesktopApplications::new()),
+                SourceKind::Windows => Box::new(WindowSource::new()),
+                SourceKind::Secrets { store } => match store {
+                    Some(store) => Box::new(Secrets::new().with_store(config_path(store))),
+                    None => Box::new(Secrets::new()),
+                },
+                SourceKind::Tmux { terminal } => match terminal {
+                    Some(terminal) => Box::new(Tmux::new().with_terminal(terminal)),
+                    None => Box::new(Tmux::new()),
+                },
+                SourceKind::Lua { path } => Box::new(
+                    LuaSource::new(config_path(path)).with_password_store(password_store.clone()),
+                ),
+            };
+            SourceEntry {
+                source,
+                prefix: entry.prefix.clone(),
             }
+        })
+        .collect();
+
+    if !config.plugins_dir.is_empty() {
+        for plugin in plugins::discover(config_path(&config.plugins_dir)) {
+            if sources
+                .iter()
+                .any(|entry| entry.source.name() == plugin.name)
+            {
+                warn!(
+                    "Skipping plugin '{}', a source has the same name",
+                    plugin.name
+                );
+                continue;
+            }
+            sources.push(plugin.into_entry(password_store.clone()));
+        }
+    }
+    sources
+}
 
-            println!("Opening existing tudo session");
-            let _ = Command::new("sh")
-                .args(["-c", &format!("kill -s USR2 {}", &pid)])
-                .spawn();
-            true
+// Screens read the config when they are built, so a reload builds them again
+fn create_screens(
+    app: &App,
+    size: (u32, u32),
+    source_manager: &SourceManager,
+    dmenu_prompt: Option<String>,
+) -> HashMap<String, Box<dyn Screen>> {
+    let mut main_screen = MainScreen::new(
+        &app.config,
+        size.0 as usize,
+        size.1 as usize,
+        source_manager.items.clone(),
+    );
+    if app.dmenu {
+        main_screen = main_screen.with_dmenu(dmenu_prompt);
+    }
+
+    let debug = DebugScreen::new(&app.config);
+    let info_screen = InfoScreen::new(&app.config, source_manager.statuses.clone());
+
+    let mut screen_map: HashMap<String, Box<dyn Screen>> = HashMap::new();
+    screen_map.insert("main".to_string(), Box::new(main_screen));
+    screen_map.insert("debug".to_string(), Box::new(debug));
+    screen_map.insert("info".to_string(), Box::new(info_screen));
+    screen_map
+}
+
+// Watches the config folder, and the plugins folder when it lives somewhere else
+fn config_watcher(config: &Config) -> Option<Watcher> {
+    let config_file = config_path("config.lua");
+    let config_dir = Path::new(&config_file)
+        .parent()?
+        .to_string_lossy()
+        .to_string();
+    let mut dirs = vec![config_dir.clone()];
+    if !config.plugins_dir.is_empty() {
+        let plugins_dir = config_path(&config.plugins_dir);
+        if !Path::new(&plugins_dir).starts_with(&config_dir) {
+            dirs.push(plugins_dir);
         }
-        _ => false,
     }
+
+    Watcher::new(vec![("config".to_string(), dirs)])
+        .inspect_err(|err| warn!("Config won't reload on changes: {}", err))
+        .ok()
+}
+
+// Re-read config.lua and apply it to everything, a broken file keeps the running config.
+// Returns whether anything was applied
+fn reload_config(
+    app: &mut App,
+    cache: &mut TextureCache,
+    source_manager: &mut SourceManager,
+) -> bool {
+    let path = config_path("config.lua");
+    let config = match try_load_config(&path) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Keeping the current config, {} has errors: {}", path, err);
+            return false;
+        }
+    };
+    info!("Reloading {}", path);
+
+    app.keymap = Keymap::from_config(&config.keys);
+    app.frame_lock = config.frame_lock;
+    cache.fonts.configure(
+        &config.font_family,
+        config.font_fallbacks.clone(),
+        config.font_configs(),
+    );
+    app.config = config;
+
+    source_manager.reload(get_sources(app.dmenu, &app.config));
+    app.loading = true;
+    true
 }
 
 fn calc() {
@@ -93,24 +238,52 @@ fn main() {
         }
     }
 
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+    // dmenu mode: `tudo --dmenu [-p <prompt>]` is a one-shot process reading items from stdin
+    let dmenu = args.first().is_some_and(|arg| arg == "--dmenu");
+    let dmenu_prompt = args
+        .iter()
+        .position(|arg| arg == "-p")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+
+    let command = match (dmenu, Command::from_args(&args)) {
+        (true, _) => Command::Show,
+        (false, Ok(command)) => command,
+        (false, Err(err)) => {
+            eprintln!("{}\n{}", err, ipc::USAGE);
+            std::process::exit(1);
+        }
+    };
+
     info!("Starting TUDO");
 
-    if check_running_state() {
+    if !dmenu && (check_running_state(&command) || command == Command::Quit) {
         return;
     };
 
-    std::fs::write(PID_FILE, std::process::id().to_string()).unwrap();
+    // Control channel, fed by the control socket and by signal handling
+    let (tx, rx) = channel::<Command>();
 
-    // Control channel for signalling handling
-    let (tx, rx) = channel::<i32>();
+    if !dmenu {
+        ipc::listen(tx.clone()).unwrap();
 
-    let mut signals = Signals::new([SIGUSR2, SIGINT]).unwrap();
+        let mut signals = Signals::new([SIGUSR2, SIGINT]).unwrap();
 
-    thread::spawn(move || {
-        for sig in signals.forever() {
-            tx.send(sig).unwrap();
-        }
-    });
+        thread::spawn(move || {
+            for sig in signals.forever() {
+                let command = match sig {
+                    SIGINT => Command::Quit,
+                    _ => Command::Show, // SIGUSR2 kept for compatibility with existing keybinds
+                };
+                tx.send(command).unwrap();
+            }
+        });
+    }
+
+    // The command this instance was started with, applied before anything from the socket
+    let mut start_command = (!dmenu).then_some(command);
 
     // First measurement and initial state
     let initial_instant = Instant::now();
@@ -125,6 +298,7 @@ fn main() {
     info!("Initializing App State");
     // Create app context and main window canvas
     let (mut app, mut main_canvas) = App::init();
+    app.dmenu = dmenu;
     info!("Finished initializing App State");
 
     // Create texture creator for the main window canvas
@@ -133,61 +307,16 @@ fn main() {
     // Create texture caches
     let mut cache = TextureCache::new(&tc, &ttf);
 
-    // Load initial fonts
-    cache.fonts.load_font(FontConfig {
-        alias: "normal-30".to_string(),
-        family: app.config.font_family.clone(),
-        point_size: 30,
-    });
-    cache.fonts.load_font(FontConfig {
-        alias: "normal-28".to_string(),
-        family: app.config.font_family.clone(),
-        point_size: 28,
-    });
-    cache.fonts.load_font(FontConfig {
-        alias: "normal-20".to_string(),
-        family: app.config.font_family.clone(),
-        point_size: 20,
-    });
-    cache.fonts.load_font(FontConfig {
-        alias: "normal-16".to_string(),
-        family: app.config.font_family.clone(),
-        point_size: 14,
-    });
+    // Fonts are loaded on first use
+    cache.fonts.configure(
+        &app.config.font_family,
+        app.config.font_fallbacks.clone(),
+        app.config.font_configs(),
+    );
 
     // Generate items list from all sources
-    let items: Arc<Mutex<Vec<SourceItem>>> = Arc::new(Mutex::new(Vec::new()));
-    let completed_threads: Arc<Mutex<u32>> = Arc::new(Mutex::new(0));
-
-    let sources: Vec<Box<dyn Source + Send>> = vec![
-        Box::new(DesktopApplications::new()),
-        Box::new(WindowSource::new()),
-        Box::new(Secrets::new()),
-        Box::new(Tmux::new()),
-        // Box::new(LuaSource::new("plugins/vlad.lua".to_string())),
-        // Box::new(DummySource::new()),
-    ];
-
-    // Get number of sources before consuming them
-    let total_threads = sources.len();
-
-    // 'async' it
-    for source in sources {
-        let i = items.clone();
-        let ct = completed_threads.clone();
-        thread::spawn(move || {
-            // Calc, ...
-            let is = source.generate_items();
-
-            // ... and then lock
-            let mut items = i.lock().unwrap();
-            items.extend(is);
-
-            // Increment completed_threads
-            let mut ct = ct.lock().unwrap();
-            *ct += 1;
-        });
-    }
+    let mut source_manager = SourceManager::new(app.config.fast_start);
+    source_manager.load(get_sources(dmenu, &app.config));
 
     // misc main loop setup
     let mut tick_time = Instant::now();
@@ -196,40 +325,99 @@ fn main() {
     let window_width = main_canvas.window().size().0 as i32;
     let window_height = main_canvas.window().size().1 as i32;
 
-    let main_screen = MainScreen::new(
-        &app.config,
-        main_canvas.window().size().0 as usize,
-        main_canvas.window().size().1 as usize,
-        items.clone(),
+    let mut screen_map = create_screens(
+        &app,
+        main_canvas.window().size(),
+        &source_manager,
+        dmenu_prompt.clone(),
     );
 
-    let debug = DebugScreen::new(&app.config);
-    let info_screen = InfoScreen::new(&app.config);
-
-    let mut screen_map: HashMap<String, Box<dyn Screen>> = HashMap::new();
-    screen_map.insert("main".to_string(), Box::new(main_screen));
-    screen_map.insert("debug".to_string(), Box::new(debug));
-    screen_map.insert("info".to_string(), Box::new(info_screen));
+    // Editing config.lua or a plugin applies it right away, dmenu mode runs too briefly to care
+    let mut config_changes = match dmenu {
+        true => None,
+        false => config_watcher(&app.config),
+    };
 
     while app.running {
-        let current_screen = screen_map.get_mut(&app.current_screen_id).unwrap();
-        let ct = completed_threads.lock().unwrap();
-        app.loading = *ct != total_threads as u32;
-        // We need to drop here in order to yield the lock
-        drop(ct);
+        // The reload key, `tudo reload` and saving a Lua file all end up here
+        let mut reload = std::mem::take(&mut app.reload_requested);
+        if let Some(watcher) = &config_changes {
+            reload |= watcher
+                .changes()
+                .iter()
+                .any(|change| change.path.ends_with(".lua"));
+        }
 
-        if app.hidden {
-            if let Ok(sig) = rx.try_recv() {
-                match sig {
-                    SIGINT => app.running = false, // This will stop the main thread when hidden
-                    // and receiving a Ctrl-C
-                    SIGUSR2 => {
-                        main_canvas.window_mut().show();
-                        app.hidden = false
+        source_manager.set_query(&app.query);
+        source_manager.poll();
+        app.loading = source_manager.loading() || app.waiting_action.is_some();
+
+        // Handle commands coming from the control socket and signals
+        loop {
+            let starting = start_command.is_some();
+            let Some(command) = start_command.take().or_else(|| rx.try_recv().ok()) else {
+                break;
+            };
+            // The socket is up before the window, a `tudo hide` or `toggle` sent meanwhile
+            // would hide it before it was ever drawn
+            if first_render && !starting && matches!(command, Command::Hide | Command::Toggle) {
+                info!("Ignoring '{}' sent before the window was shown", command);
+                continue;
+            }
+
+            let mut show = false;
+            match command {
+                Command::Show => show = true,
+                Command::Hide => app.should_hide = !app.hidden,
+                Command::Toggle => match app.hidden {
+                    true => show = true,
+                    false => app.should_hide = true,
+                },
+                Command::Quit => app.running = false,
+                Command::Screen(id) => {
+                    if screen_map.contains_key(&id) {
+                        app.current_screen_id = id;
+                        show = true;
+                    } else {
+                        warn!("Unknown screen '{}'", id);
                     }
-                    _ => (),
                 }
+                Command::Prompt(text) => {
+                    app.current_screen_id = "main".to_string();
+                    app.prompt = Some(text);
+                    show = true;
+                }
+                Command::Reload => reload = true,
+            }
+
+            if show && app.hidden {
+                main_canvas.window_mut().show();
+                app.hidden = false;
+                source_manager.on_show();
             }
+        }
+
+        // Runs still going are told apart by their run id, so nothing waits for them
+        if reload && !dmenu && reload_config(&mut app, &mut cache, &mut source_manager) {
+            screen_map = create_screens(
+                &app,
+                main_canvas.window().size(),
+                &source_manager,
+                dmenu_prompt.clone(),
+            );
+            // The new main screen starts without a menu open
+            app.action_menu_open = false;
+            config_changes = config_watcher(&app.config);
+        }
+
+        // Key bindings can name any screen
+        if !screen_map.contains_key(&app.current_screen_id) {
+            warn!("Unknown screen '{}'", app.current_screen_id);
+            app.current_screen_id = "main".to_string();
+        }
+        let current_screen = screen_map.get_mut(&app.current_screen_id).unwrap();
+
+        if app.hidden {
             sleep(Duration::from_millis(10));
         }
 
@@ -251,13 +439,23 @@ fn main() {
 
         // Handle application global events
         app.handle_global_events(&cur_events);
-        if app.should_hide {
+        if app.should_hide && app.dmenu {
+            // Nothing was picked
+            app.exit_code = 1;
+            app.running = false;
+        } else if app.should_hide {
             main_canvas.window_mut().hide();
-            current_screen.reset();
+            current_screen.reset(&app);
+            app.waiting_action = None;
+            app.action_menu_open = false;
             app.should_hide = false;
             app.hidden = true
         }
 
+        if let Some(action) = app.waiting_action.take() {
+            action.execute(&mut app);
+        }
+
         // Screen update
         current_screen.update(&mut app, &cur_events, elapsed);
 
@@ -266,7 +464,7 @@ fn main() {
 
         // Draw info directly into the canvas
         if app.draw_fps {
-            let font = &cache.fonts.get_font("normal-20");
+            let font = &cache.fonts.get_font(UI_FONT);
 
             draw_string(
                 format!("{}", fps).to_string(),
@@ -282,7 +480,7 @@ fn main() {
 
         if first_render {
             first_render = false;
-            println!(
+            info!(
                 "Time to first render: {}ms",
                 initial_instant.elapsed().as_millis()
             )
@@ -295,24 +493,14 @@ fn main() {
                 Duration::new(0, (1000 / frame_lock_value) * 1_000_000) - tick_time.elapsed(),
             );
         }
-        if app.clipboard.is_some() {
-            let cmd = match std::env::var("XDG_SESSION_TYPE") {
-                Ok(session) => match session.borrow() {
-                    "wayland" => "wl-copy",
-                    "x11" => "xsel --clipboard --input",
-                    _ => "",
-                },
-                _ => "",
-            };
-            let full_cmd = format!(
-                r"echo -n {} | {}",
-                app.clipboard.clone().unwrap().replace("\n", ""),
-                cmd
-            );
-
-            let _ = Command::new("sh").arg("-c").arg(full_cmd).spawn();
-            app.clipboard = None;
+        if let Some(text) = app.clipboard.take() {
+            if let Err(err) = set_clipboard(&text.replace("\n", "")) {
+                warn!("Can't copy to the clipboard: {}", err);
+            }
         }
     }
-    std::fs::remove_file(PID_FILE).unwrap();
+    if dmenu {
+        std::process::exit(app.exit_code);
+    }
+    ipc::cleanup();
 }
@@ -8,30 +8,48 @@ use sdl2::{
 use crate::{
     app::App,
     config::Config,
+    sources::manager::{SourceStatus, SourceStatuses},
     ui::components::label::Label,
     ui::layout::{ContainerSize, LayoutBuilder, SplitType},
-    utils::cache::TextureCache,
+    ui::theme::{Theme, UI_FONT},
+    utils::{cache::TextureCache, draw::draw_string_texture_canvas},
 };
 
 use super::Screen;
 
+const LINE_HEIGHT: i32 = 30;
+
+// Shows how each source is doing, so a broken one doesn't just leave the list empty
 pub struct InfoScreen {
     layout: LayoutBuilder,
+    statuses: SourceStatuses,
+}
+
+fn status_line(name: &str, status: &SourceStatus, theme: &Theme) -> (String, Color) {
+    match status {
+        SourceStatus::Loading => (format!("{}: loading", name), theme.muted),
+        SourceStatus::Ready { items, elapsed } => (
+            format!("{}: {} items in {}ms", name, items, elapsed.as_millis()),
+            theme.foreground,
+        ),
+        SourceStatus::Failed(err) => (format!("{}: {}", name, err), theme.error),
+    }
 }
 
 impl InfoScreen {
-    pub fn new(_config: &Config) -> InfoScreen {
+    pub fn new(_config: &Config, statuses: SourceStatuses) -> InfoScreen {
         let mut builder = LayoutBuilder::new();
 
         let _main_split = builder.add_split(SplitType::Vertical, ContainerSize::Percent(100));
-        for s in [String::from("foo"), String::from("bar")] {
-            builder.add(
-                Box::new(Label::new(s.clone(), s.clone())),
-                ContainerSize::Percent(20),
-            );
-        }
+        builder.add(
+            Box::new(Label::new("title", "Sources")),
+            ContainerSize::Fixed(40),
+        );
 
-        InfoScreen { layout: builder }
+        InfoScreen {
+            layout: builder,
+            statuses,
+        }
     }
 }
 
@@ -56,12 +74,25 @@ impl Screen for InfoScreen {
         self.layout.generate(width as usize, height as usize);
 
         // Set draw color and clear
-        let clear_color = Color::RGBA(24, 24, 33, 255);
+        let clear_color = app.config.theme.background;
         main_canvas.set_draw_color(clear_color);
         main_canvas.clear();
 
         for (rect, component) in self.layout.components_with_rect() {
             component.draw(texture_creator, cache, app, main_canvas, rect, elapsed);
         }
+
+        let font = cache.fonts.get_font(UI_FONT);
+        for (idx, (name, status)) in self.statuses.lock().unwrap().iter().enumerate() {
+            let (line, color) = status_line(name, status, &app.config.theme);
+            draw_string_texture_canvas(
+                main_canvas,
+                10,
+                50 + idx as i32 * LINE_HEIGHT,
+                line,
+                font,
+                color,
+            );
+        }
     }
 }
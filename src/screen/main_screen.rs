@@ -1,10 +1,11 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use mlua::Lua;
 use sdl2::{
     event::Event,
-    keyboard::Keycode,
-    pixels::Color,
     render::{Canvas, TextureCreator},
     video::{Window, WindowContext},
 };
@@ -13,7 +14,11 @@ use crate::{
     app::App,
     config::Config,
     execute,
-    sources::SourceItem,
+    keymap::Command,
+    sources::{
+        actions::{Action, PrintAction},
+        SourceItem,
+    },
     ui::{
         components::{
             clock::Clock,
@@ -35,6 +40,10 @@ use super::Screen;
 pub struct MainScreen {
     layout: LayoutBuilder,
     source_items: Arc<Mutex<Vec<SourceItem>>>,
+    // Last known history scores, used when resetting without access to the app
+    frecency: Arc<HashMap<String, u32>>,
+    // Actions of the selected item and the prompt to restore once the menu is closed
+    action_menu: Option<(Vec<SourceItem>, String)>,
 }
 
 fn calc(script: impl AsRef<str>) -> Result<f32, String> {
@@ -59,7 +68,9 @@ impl MainScreen {
         items: Arc<Mutex<Vec<SourceItem>>>,
     ) -> MainScreen {
         let prompt = TextInput::new("prompt", config);
-        let select_list = SelectList::<SourceItem>::new("list").with_on_select(execute);
+        let select_list = SelectList::<SourceItem>::new("list")
+            .with_on_select(execute)
+            .with_theme(&config.theme);
         let spinner = Spinner::new("spinner".to_string());
         let clock = Clock::new("clock".to_string());
         let workspaces = Workspaces::new("workspaces".to_string());
@@ -86,12 +97,87 @@ impl MainScreen {
         MainScreen {
             layout: builder,
             source_items: items,
+            frecency: Arc::default(),
+            action_menu: None,
         }
     }
+
+    fn open_action_menu(&mut self, app: &mut App) {
+        let Some(item) = self
+            .layout
+            .by_name_typed::<SelectList<SourceItem>>("list")
+            .get_selected_item()
+            .cloned()
+        else {
+            return;
+        };
+
+        self.open_menu(item.action_items(), app);
+    }
+
+    // Lists `items` in place of the source items until the menu is closed
+    fn open_menu(&mut self, items: Vec<SourceItem>, app: &mut App) {
+        let prompt = self.layout.by_name_typed::<TextInput>("prompt");
+        let prompt_text = prompt.state.text.clone();
+        prompt.clear();
+
+        // Items are listed in the order they were given
+        let list = self.layout.by_name_typed::<SelectList<SourceItem>>("list");
+        list.keep_order = true;
+        list.set_selected_index(0);
+
+        // A menu opened from a menu goes back to the prompt of the first one
+        let prompt_text = match self.action_menu.take() {
+            Some((_, previous)) => previous,
+            None => prompt_text,
+        };
+        self.action_menu = Some((items, prompt_text));
+        app.action_menu_open = true;
+    }
+
+    fn close_action_menu(&mut self, app: &mut App) {
+        let Some((_, prompt_text)) = self.action_menu.take() else {
+            return;
+        };
+
+        let prompt = self.layout.by_name_typed::<TextInput>("prompt");
+        prompt.clear();
+        prompt.set_text(prompt_text);
+        prompt.cursor_end();
+
+        let list = self.layout.by_name_typed::<SelectList<SourceItem>>("list");
+        list.keep_order = app.dmenu;
+        list.set_selected_index(0);
+
+        app.action_menu_open = false;
+    }
+
+    // dmenu mode keeps the items in the order they were read
+    pub fn with_dmenu(mut self, input_hint: Option<String>) -> Self {
+        self.layout.by_name_typed::<TextInput>("prompt").input_hint = input_hint;
+        self.layout
+            .by_name_typed::<SelectList<SourceItem>>("list")
+            .keep_order = true;
+        self
+    }
 }
 
 impl Screen for MainScreen {
     fn update(&mut self, app: &mut App, events: &Vec<Event>, elapsed: u128) {
+        // Prompt pre-filled from outside, e.g. `tudo prompt :window`
+        if let Some(text) = app.prompt.take() {
+            let prompt = self.layout.by_name_typed::<TextInput>("prompt");
+            prompt.clear();
+            prompt.set_text(text);
+            prompt.cursor_end();
+        }
+
+        if let Some(items) = app.pushed_items.take() {
+            self.open_menu(items, app);
+        }
+
+        self.frecency = app.history.scores();
+
         let prompt_text = self
             .layout
             .by_name_typed::<TextInput>("prompt")
@@ -99,11 +185,25 @@ impl Screen for MainScreen {
             .text
             .clone();
 
+        // Menus list their own items, the query stays what it was
+        if self.action_menu.is_none() {
+            app.query = match prompt_text.strip_prefix(":") {
+                Some(tagged) => tagged.split_once(" ").map_or("", |(_, text)| text),
+                None => prompt_text.as_str(),
+            }
+            .to_string();
+        }
+
+        let items = match &self.action_menu {
+            Some((action_items, _)) => action_items.clone(),
+            None => self.source_items.lock().unwrap().clone(),
+        };
         self.layout
             .by_name("list".to_string())
             .set_state(Box::new(SelectListState {
-                items: self.source_items.lock().unwrap().clone(),
+                items,
                 prompt: prompt_text.clone(),
+                frecency: self.frecency.clone(),
             }));
 
         for event in events.iter() {
@@ -121,23 +221,54 @@ impl Screen for MainScreen {
                         }
                     }
                 }
-                sdl2::event::Event::KeyUp {
-                    keycode: Some(Keycode::F5),
-                    ..
-                } => {
-                    let result = self.layout.by_name_typed::<Label>("result");
-
-                    if let Ok(res) = calc(&prompt_text) {
-                        result.text = res.to_string();
-                        self.layout.by_name_container("list").hidden = true; // Toggle
-                        self.layout.by_name_container("result").hidden = false;
+                _ => match app.keymap.lookup(event) {
+                    Some(Command::Calc) => {
+                        let result = self.layout.by_name_typed::<Label>("result");
+
+                        if let Ok(res) = calc(&prompt_text) {
+                            result.text = res.to_string();
+                            self.layout.by_name_container("list").hidden = true; // Toggle
+                            self.layout.by_name_container("result").hidden = false;
+                        }
                     }
-                }
-                _ => {
-                    for component in self.layout.components() {
-                        component.handle_event(event, app, elapsed);
+                    Some(Command::ActionsMenu) if !app.dmenu && self.action_menu.is_none() => {
+                        self.open_action_menu(app)
                     }
-                }
+                    Some(Command::Back) if self.action_menu.is_some() => {
+                        self.close_action_menu(app)
+                    }
+                    // In dmenu mode the secondary action, or executing without any match,
+                    // prints the typed text
+                    Some(command @ (Command::Execute | Command::ExecuteSecondary))
+                        if app.dmenu
+                            && (command == Command::ExecuteSecondary
+                                || self
+                                    .layout
+                                    .by_name_typed::<SelectList<SourceItem>>("list")
+                                    .get_selected_item()
+                                    .is_none()) =>
+                    {
+                        PrintAction {
+                            text: prompt_text.clone(),
+                        }
+                        .execute(app);
+                    }
+                    // Same path as picking it from the actions menu, so it counts in history
+                    Some(Command::ExecuteSecondary) if self.action_menu.is_none() => {
+                        let list = self.layout.by_name_typed::<SelectList<SourceItem>>("list");
+                        if let Some(item) = list
+                            .get_selected_item()
+                            .and_then(|item| item.action_items().get(1).cloned())
+                        {
+                            execute(&item, app);
+                        }
+                    }
+                    _ => {
+                        for component in self.layout.components() {
+                            component.handle_event(event, app, elapsed);
+                        }
+                    }
+                },
             }
         }
         for component in self.layout.components() {
@@ -171,7 +302,7 @@ impl Screen for MainScreen {
         self.layout.generate(width as usize, height as usize);
 
         // Set draw color and clear
-        let clear_color = Color::RGBA(24, 24, 33, 255);
+        let clear_color = app.config.theme.background;
         main_canvas.set_draw_color(clear_color);
         main_canvas.clear();
 
@@ -179,13 +310,19 @@ impl Screen for MainScreen {
             component.draw(texture_creator, cache, app, main_canvas, rect, elapsed);
         }
     }
-    fn reset(&mut self) {
+    fn reset(&mut self, app: &App) {
+        if self.action_menu.take().is_some() {
+            self.layout
+                .by_name_typed::<SelectList<SourceItem>>("list")
+                .keep_order = app.dmenu;
+        }
         self.layout.by_name_typed::<TextInput>("prompt").clear();
         self.layout
             .by_name("list".to_string())
             .set_state(Box::new(SelectListState {
                 items: self.source_items.lock().unwrap().clone(),
                 prompt: "".to_string(),
+                frecency: self.frecency.clone(),
             }));
     }
 }
@@ -23,5 +23,5 @@ pub trait Screen {
         elapsed: u128,
     );
 
-    fn reset(&mut self) {}
+    fn reset(&mut self, _app: &App) {}
 }
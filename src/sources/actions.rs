@@ -3,39 +3,52 @@ use std::process::Command;
 use dyn_clone::DynClone;
 use xcb::x::Window;
 
+use crate::utils::misc::shell_quote;
 use crate::App;
 
-use super::windows::switch_to_window;
+use super::cache::CachedAction;
+use super::windows::{close_window, move_window_to_desktop, switch_to_window};
 
 pub trait Action: DynClone {
     fn execute(&self, ctx: &mut App);
     fn tags(&self) -> Vec<String>;
+    // Label shown in the actions menu
+    fn name(&self) -> String;
+    // Serializable form for the on-disk index, None for actions that can't outlive the process
+    fn cached(&self) -> Option<CachedAction> {
+        None
+    }
 }
 dyn_clone::clone_trait_object!(Action);
 
+// Pointed at the store the secret comes from, pass' own default otherwise
+fn in_store(mut command: Command, store: &Option<String>) -> Command {
+    if let Some(store) = store {
+        command.env("PASSWORD_STORE_DIR", store);
+    }
+    command
+}
+
+fn pass(store: &Option<String>, args: &[&str]) -> String {
+    let mut command = Command::new("pass");
+    command.args(args);
+    let output = in_store(command, store).output();
+    String::from_utf8(output.unwrap().stdout).unwrap()
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct PassSecretAction {
     pub secret_name: String,
+    // Password store directory, None for pass' default
+    pub store: Option<String>,
 }
 
 impl Action for PassSecretAction {
     fn execute(&self, ctx: &mut App) {
-        let pass_args = vec![
-            "-c".to_string(),
-            format!("pass {}", self.secret_name.to_string()),
-        ];
-        let pass_otp_args = vec![
-            "-c".to_string(),
-            format!("pass otp {}", self.secret_name.to_string()),
-        ];
-
-        let output = Command::new("sh").args(pass_args.clone()).output();
-        let ot = String::from_utf8(output.unwrap().stdout).unwrap();
+        let ot = pass(&self.store, &[&self.secret_name]);
 
         if ot.starts_with("otpauth://") {
-            let otp_output = Command::new("sh").args(pass_otp_args.clone()).output();
-            let oot = String::from_utf8(otp_output.unwrap().stdout).unwrap();
-            ctx.clipboard = Some(oot.clone());
+            ctx.clipboard = Some(pass(&self.store, &["otp", &self.secret_name]));
         } else {
             ctx.clipboard = Some(ot);
         }
@@ -44,6 +57,121 @@ impl Action for PassSecretAction {
     fn tags(&self) -> Vec<String> {
         vec!["secret".to_string()]
     }
+    fn name(&self) -> String {
+        "Copy password".to_string()
+    }
+    fn cached(&self) -> Option<CachedAction> {
+        Some(CachedAction::PassSecret {
+            secret_name: self.secret_name.clone(),
+            store: self.store.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassOtpAction {
+    pub secret_name: String,
+    // Password store directory, None for pass' default
+    pub store: Option<String>,
+}
+
+impl Action for PassOtpAction {
+    fn execute(&self, ctx: &mut App) {
+        ctx.clipboard = Some(pass(&self.store, &["otp", &self.secret_name]));
+        ctx.should_hide = true;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["secret".to_string()]
+    }
+    fn name(&self) -> String {
+        "Copy OTP".to_string()
+    }
+    fn cached(&self) -> Option<CachedAction> {
+        Some(CachedAction::PassOtp {
+            secret_name: self.secret_name.clone(),
+            store: self.store.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassUsernameAction {
+    pub secret_name: String,
+    // Password store directory, None for pass' default
+    pub store: Option<String>,
+}
+
+impl Action for PassUsernameAction {
+    fn execute(&self, ctx: &mut App) {
+        // Look for a "login: foo" style line, otherwise follow the common convention of naming
+        // the secret after the username, e.g. "github.com/foo"
+        let contents = pass(&self.store, &[&self.secret_name]);
+        let username = contents
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(":"))
+            .find(|(key, _)| {
+                ["login", "user", "username"].contains(&key.trim().to_lowercase().as_str())
+            })
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_else(|| self.secret_name.split("/").last().unwrap().to_string());
+
+        ctx.clipboard = Some(username);
+        ctx.should_hide = true;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["secret".to_string()]
+    }
+    fn name(&self) -> String {
+        "Copy username".to_string()
+    }
+    fn cached(&self) -> Option<CachedAction> {
+        Some(CachedAction::PassUsername {
+            secret_name: self.secret_name.clone(),
+            store: self.store.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTypeAction {
+    pub secret_name: String,
+    // Password store directory, None for pass' default
+    pub store: Option<String>,
+}
+
+impl Action for PassTypeAction {
+    fn execute(&self, ctx: &mut App) {
+        let typer = match std::env::var("XDG_SESSION_TYPE").as_deref() {
+            Ok("wayland") => "wtype -",
+            _ => "xdotool type --clearmodifiers --file -",
+        };
+
+        // Give the window time to hide so the keystrokes land in the previously focused one
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            &format!(
+                "sleep 0.3; pass show {} | head -n 1 | tr -d '\\n' | {}",
+                shell_quote(&self.secret_name),
+                typer
+            ),
+        ]);
+        let _ = in_store(command, &self.store).spawn();
+        ctx.should_hide = true;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["secret".to_string()]
+    }
+    fn name(&self) -> String {
+        "Type password".to_string()
+    }
+    fn cached(&self) -> Option<CachedAction> {
+        Some(CachedAction::PassType {
+            secret_name: self.secret_name.clone(),
+            store: self.store.clone(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -51,6 +179,7 @@ pub struct RunAction {
     pub path: String,
     pub exit_after: bool,
     pub clip_output: bool,
+    pub label: Option<String>,
 }
 
 impl Action for RunAction {
@@ -72,6 +201,17 @@ impl Action for RunAction {
     fn tags(&self) -> Vec<String> {
         vec!["run".to_string()]
     }
+    fn name(&self) -> String {
+        self.label.clone().unwrap_or("Run".to_string())
+    }
+    fn cached(&self) -> Option<CachedAction> {
+        Some(CachedAction::Run {
+            path: self.path.clone(),
+            exit_after: self.exit_after,
+            clip_output: self.clip_output,
+            label: self.label.clone(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -93,17 +233,67 @@ impl Action for WindowSwitchAction {
     fn tags(&self) -> Vec<String> {
         vec!["window".to_string()]
     }
+    fn name(&self) -> String {
+        "Focus".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowCloseAction {
+    pub window: Window,
+}
+
+impl Action for WindowCloseAction {
+    fn execute(&self, ctx: &mut App) {
+        let (conn, _) = xcb::Connection::connect(None).unwrap();
+        let root = conn.get_setup().roots().nth(0).unwrap().root();
+        let _ = close_window(&conn, &self.window, &root);
+
+        ctx.should_hide = true;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["window".to_string()]
+    }
+    fn name(&self) -> String {
+        "Close".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowMoveAction {
+    pub window: Window,
+    pub desktop: u32,
+}
+
+impl Action for WindowMoveAction {
+    fn execute(&self, ctx: &mut App) {
+        let (conn, _) = xcb::Connection::connect(None).unwrap();
+        let root = conn.get_setup().roots().nth(0).unwrap().root();
+        let _ = move_window_to_desktop(&conn, &self.window, &root, self.desktop);
+
+        ctx.should_hide = true;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["window".to_string()]
+    }
+    fn name(&self) -> String {
+        format!("Move to workspace {}", self.desktop + 1)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TmuxAction {
     pub session: String,
+    pub terminal: String,
 }
 
 impl Action for TmuxAction {
     fn execute(&self, ctx: &mut App) {
         Command::new("sh")
-            .args(["-c", &format!("alacritty -e tmux new -As {}", self.session)])
+            .args([
+                "-c",
+                &format!("{} tmux new -As {}", self.terminal, self.session),
+            ])
             .spawn()
             .unwrap();
         ctx.should_hide = true;
@@ -111,4 +301,26 @@ impl Action for TmuxAction {
     fn tags(&self) -> Vec<String> {
         vec!["tmux".to_string()]
     }
+    fn name(&self) -> String {
+        "Attach".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintAction {
+    pub text: String,
+}
+
+impl Action for PrintAction {
+    fn execute(&self, ctx: &mut App) {
+        println!("{}", self.text);
+        ctx.exit_code = 0;
+        ctx.running = false;
+    }
+    fn tags(&self) -> Vec<String> {
+        vec!["stdin".to_string()]
+    }
+    fn name(&self) -> String {
+        "Print".to_string()
+    }
 }
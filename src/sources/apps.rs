@@ -1,8 +1,11 @@
-use std::fs;
+use std::{collections::HashSet, fs, path::Path};
+
+use log::{debug, warn};
 
 use crate::{
-    sources::{Source, SourceItem},
-    utils::xdg::{parse_ini_file, IconFinder},
+    sources::{ItemSink, Source, SourceError, SourceItem},
+    utils::misc::shell_quote,
+    utils::xdg::{current_locale, locale_variants, parse_ini_file, IconFinder, Section},
 };
 
 use super::actions::RunAction;
@@ -20,86 +23,249 @@ impl DesktopApplications {
         DesktopApplications {}
     }
 }
+
+// $XDG_DATA_HOME first, so user entries take precedence over system ones with the same ID
+fn application_dirs() -> Vec<String> {
+    let home = std::env::var("HOME").unwrap();
+    let data_home =
+        std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!("{}/.local/share", home));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share".to_string());
+
+    std::iter::once(data_home.as_str())
+        .chain(data_dirs.split(":"))
+        .map(|dir| format!("{}/applications", dir))
+        .collect()
+}
+
+// Collect (desktop file ID, path) pairs. The ID is the path relative to the applications dir
+// with "/" replaced by "-", e.g. kde/konsole.desktop is kde-konsole.desktop
+fn find_desktop_files(base: &Path, dir: &Path, files: &mut Vec<(String, String)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_desktop_files(base, &path, files);
+        } else if path.extension().is_some_and(|ext| ext == "desktop") {
+            let id = path
+                .strip_prefix(base)
+                .unwrap()
+                .to_string_lossy()
+                .replace("/", "-");
+            files.push((id, path.to_string_lossy().to_string()));
+        }
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    if program.contains("/") {
+        return Path::new(program).exists();
+    }
+    std::env::var("PATH")
+        .unwrap_or_default()
+        .split(":")
+        .any(|dir| Path::new(dir).join(program).exists())
+}
+
+// Whether the entry should be shown in the current desktop environment
+fn shown_in(group: &Section, desktops: &[String]) -> bool {
+    let only_show_in = group.get_list("OnlyShowIn");
+    if !only_show_in.is_empty() && !desktops.iter().any(|d| only_show_in.contains(d)) {
+        return false;
+    }
+    let not_show_in = group.get_list("NotShowIn");
+    !desktops.iter().any(|d| not_show_in.contains(d))
+}
+
+// Expand Exec field codes. We never launch with files or URLs, so those codes are dropped
+fn expand_field_codes(exec: &str, name: &str, icon: Option<&str>, file: &str) -> String {
+    let mut res = String::new();
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => res.push('%'),
+            Some('i') => {
+                if let Some(icon) = icon {
+                    res.push_str(&format!("--icon {}", shell_quote(icon)));
+                }
+            }
+            Some('c') => res.push_str(&shell_quote(name)),
+            Some('k') => res.push_str(&shell_quote(file)),
+            // %f %F %u %U and the deprecated %d %D %n %N %v %m
+            _ => (),
+        }
+    }
+    res.trim().to_string()
+}
+
+fn terminal_command(exec: String) -> String {
+    let terminal = std::env::var("TERMINAL").unwrap_or("xterm".to_string());
+    format!("{} -e {}", terminal, exec)
+}
+
 impl Source for DesktopApplications {
+    fn name(&self) -> String {
+        "apps".to_string()
+    }
     fn is_async(&self) -> bool {
         false
     }
-    fn generate_items(&self) -> Vec<SourceItem> {
+    // Icon themes are left out, watching /usr/share/icons takes thousands of inotify watches.
+    // Icons are resolved again on every scan, so new apps bring theirs along
+    fn watched_dirs(&self) -> Vec<String> {
+        application_dirs()
+    }
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
         let mut res: Vec<SourceItem> = Vec::new();
 
         let icon_finder = IconFinder::new();
-        let home = std::env::var("HOME").unwrap();
-        let paths = match std::env::var("XDG_DATA_DIRS") {
-            Ok(dirs) => dirs
-                .split(":")
-                .map(|x| format!("{}/applications", x))
-                .collect::<Vec<String>>(),
-            Err(_) => vec![
-                "/usr/share/applications".to_string(),
-                format!("/home/{}/.local/share/applications", home).to_string(),
-            ],
-        };
-
-        for path in paths {
-            let desktop_files = match fs::read_dir(path) {
-                Ok(entries) => entries
-                    .filter(|entry| {
-                        entry
-                            .as_ref()
-                            .unwrap()
-                            .file_name()
-                            .into_string()
-                            .unwrap()
-                            .ends_with(".desktop")
-                    })
-                    .map(|entry| {
-                        entry
-                            .as_ref()
-                            .unwrap()
-                            .path()
-                            .into_os_string()
-                            .into_string()
-                            .unwrap()
-                    })
-                    .collect::<Vec<String>>(),
-
-                Err(_) => Vec::new(),
-            };
-
-            for file in desktop_files.iter() {
-                let desk_entry = parse_ini_file(file.to_string()).unwrap();
-
-                let title = desk_entry.get("Desktop Entry").unwrap().get("Name");
-                let action = desk_entry.get("Desktop Entry").unwrap().get("Exec");
-
-                let icon = match desk_entry.get("Desktop Entry").unwrap().get("Icon") {
-                    Some(_icon) => icon_finder.get_icon_with_size(_icon.to_string(), 32),
-                    None => None,
+        let locales = locale_variants(&current_locale());
+        let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .split(":")
+            .filter(|d| !d.is_empty())
+            .map(|d| d.to_string())
+            .collect();
+
+        // Only the first entry found for a desktop file ID counts, even if it is hidden,
+        // that's how users mask system entries
+        let mut seen_ids: HashSet<String> = HashSet::new();
+
+        for dir in application_dirs() {
+            let mut desktop_files = Vec::new();
+            find_desktop_files(Path::new(&dir), Path::new(&dir), &mut desktop_files);
+
+            for (desktop_id, file) in desktop_files {
+                if !seen_ids.insert(desktop_id.clone()) {
+                    continue;
+                }
+
+                let desk_entry = match parse_ini_file(&file) {
+                    Ok(desk_entry) => desk_entry,
+                    Err(err) => {
+                        warn!("Skipping desktop file: {}", err);
+                        continue;
+                    }
+                };
+                let Some(entry) = desk_entry.group("Desktop Entry") else {
+                    debug!("{} has no [Desktop Entry] group", file);
+                    continue;
+                };
+
+                if entry.get_string("Type").is_some_and(|t| t != "Application")
+                    || entry.get_boolean("Hidden").unwrap_or(false)
+                    || entry.get_boolean("NoDisplay").unwrap_or(false)
+                    || !shown_in(entry, &desktops)
+                {
+                    continue;
+                }
+                if let Some(try_exec) = entry.get_string("TryExec") {
+                    if !in_path(&try_exec) {
+                        continue;
+                    }
+                }
+
+                let (Some(title), Some(exec)) = (
+                    entry.get_localestring("Name", &locales),
+                    entry.get_string("Exec"),
+                ) else {
+                    debug!("{} file doesnt have a Name or Exec", file);
+                    continue;
                 };
 
-                if title.is_some() && action.is_some() {
-                    // Discard %U in this context since we don't have anything to interpolate
-                    let sanitized_action = action.unwrap().to_string().replace("%U", "");
+                let icon_name = entry.get_string("Icon");
+                let icon = icon_name
+                    .as_ref()
+                    .and_then(|icon| icon_finder.get_icon_with_size(icon.to_string(), 32));
+                let terminal = entry.get_boolean("Terminal").unwrap_or(false);
 
-                    res.push(SourceItem {
-                        icon,
-                        title: title.unwrap().to_string(),
-                        action: Box::new(RunAction {
-                            path: sanitized_action,
+                let command = |exec: &str| {
+                    let command = expand_field_codes(exec, &title, icon_name.as_deref(), &file);
+                    match terminal {
+                        true => terminal_command(command),
+                        false => command,
+                    }
+                };
+
+                let mut keywords = entry.get_locale_list("Keywords", &locales);
+                if let Some(generic_name) = entry.get_localestring("GenericName", &locales) {
+                    keywords.push(generic_name);
+                }
+
+                let mut item = SourceItem::new(
+                    self.name(),
+                    &title,
+                    Box::new(RunAction {
+                        path: command(&exec),
+                        exit_after: true,
+                        clip_output: false,
+                        label: Some("Launch".to_string()),
+                    }),
+                )
+                .with_id(&desktop_id)
+                .with_icon(icon)
+                .with_subtitle(entry.get_localestring("Comment", &locales))
+                .with_keywords(keywords);
+
+                // Additional application actions, e.g. "New Private Window"
+                for action_id in entry.get_list("Actions") {
+                    let Some(group) = desk_entry.group(&format!("Desktop Action {}", action_id))
+                    else {
+                        continue;
+                    };
+                    if let (Some(name), Some(exec)) = (
+                        group.get_localestring("Name", &locales),
+                        group.get_string("Exec"),
+                    ) {
+                        item = item.with_action(Box::new(RunAction {
+                            path: command(&exec),
                             exit_after: true,
                             clip_output: false,
-                        }),
-                    });
-                } else {
-                    println!(
-                        "{} file doesnt have some info. {} {}",
-                        file,
-                        title.is_some(),
-                        action.is_some()
-                    );
+                            label: Some(name),
+                        }));
+                    }
                 }
+
+                res.push(item);
+            }
+
+            // One batch per application directory
+            if !sink.send(std::mem::take(&mut res)) {
+                break;
             }
         }
-        res
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_field_codes() {
+        let icon = Some("firefox");
+        assert_eq!(
+            expand_field_codes("firefox %u", "Firefox", icon, "/a.desktop"),
+            "firefox"
+        );
+        assert_eq!(
+            expand_field_codes("app %F --name %c %i %k 100%%", "My App", icon, "/a.desktop"),
+            "app  --name 'My App' --icon 'firefox' '/a.desktop' 100%"
+        );
+        assert_eq!(
+            expand_field_codes("app %i", "App", None, "/a.desktop"),
+            "app"
+        );
     }
 }
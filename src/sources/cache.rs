@@ -0,0 +1,308 @@
+use std::{collections::HashMap, fs, path::Path, time::UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use ureq::serde_json;
+
+use super::{
+    actions::{
+        Action, PassOtpAction, PassSecretAction, PassTypeAction, PassUsernameAction, RunAction,
+    },
+    SourceItem,
+};
+
+// Bump when the layout below changes, older indexes are then ignored
+const INDEX_VERSION: u32 = 2;
+
+// Serializable form of the actions that survive a restart. Actions bound to live state, like
+// a window handle, are not cached
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CachedAction {
+    Run {
+        path: String,
+        exit_after: bool,
+        clip_output: bool,
+        label: Option<String>,
+    },
+    PassSecret {
+        secret_name: String,
+        store: Option<String>,
+    },
+    PassOtp {
+        secret_name: String,
+        store: Option<String>,
+    },
+    PassUsername {
+        secret_name: String,
+        store: Option<String>,
+    },
+    PassType {
+        secret_name: String,
+        store: Option<String>,
+    },
+}
+
+impl CachedAction {
+    pub fn into_action(self) -> Box<dyn Action + Send> {
+        match self {
+            CachedAction::Run {
+                path,
+                exit_after,
+                clip_output,
+                label,
+            } => Box::new(RunAction {
+                path,
+                exit_after,
+                clip_output,
+                label,
+            }),
+            CachedAction::PassSecret { secret_name, store } => {
+                Box::new(PassSecretAction { secret_name, store })
+            }
+            CachedAction::PassOtp { secret_name, store } => {
+                Box::new(PassOtpAction { secret_name, store })
+            }
+            CachedAction::PassUsername { secret_name, store } => {
+                Box::new(PassUsernameAction { secret_name, store })
+            }
+            CachedAction::PassType { secret_name, store } => {
+                Box::new(PassTypeAction { secret_name, store })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedItem {
+    pub id: String,
+    pub source: String,
+    pub icon: Option<String>,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub keywords: Vec<String>,
+    pub score_boost: i32,
+    pub actions: Vec<CachedAction>,
+}
+
+impl CachedItem {
+    // None if any of the item actions can't be cached
+    pub fn from_item(item: &SourceItem) -> Option<CachedItem> {
+        Some(CachedItem {
+            id: item.id.clone(),
+            source: item.source.clone(),
+            icon: item.icon.clone(),
+            title: item.title.clone(),
+            subtitle: item.subtitle.clone(),
+            keywords: item.keywords.clone(),
+            score_boost: item.score_boost,
+            actions: item
+                .actions
+                .iter()
+                .map(|action| action.cached())
+                .collect::<Option<Vec<CachedAction>>>()?,
+        })
+    }
+
+    pub fn into_item(self) -> SourceItem {
+        SourceItem {
+            id: self.id,
+            source: self.source,
+            // Resolved on an earlier run, the icon theme may have changed since
+            icon: self.icon.filter(|icon| Path::new(icon).exists()),
+            title: self.title,
+            subtitle: self.subtitle,
+            keywords: self.keywords,
+            score_boost: self.score_boost,
+            // Set again by the manager, the configuration may have changed since
+            prefix: None,
+            from_query: false,
+            run: 0,
+            actions: self
+                .actions
+                .into_iter()
+                .map(|action| action.into_action())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceIndex {
+    pub items: Vec<CachedItem>,
+    // Modification time of every watched directory when the items were generated
+    pub mtimes: HashMap<String, u128>,
+}
+
+impl SourceIndex {
+    // `mtimes` as returned by dir_mtimes for the watched directories
+    pub fn is_fresh(&self, mtimes: &HashMap<String, u128>) -> bool {
+        &self.mtimes == mtimes
+    }
+}
+
+// Items of the sources that scan the filesystem, written after every successful scan so the
+// next start can show them right away
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    pub sources: HashMap<String, SourceIndex>,
+    #[serde(skip)]
+    path: Option<String>,
+}
+
+pub fn default_path() -> String {
+    let cache_home = std::env::var("XDG_CACHE_HOME").unwrap_or_else(|_| {
+        let home = std::env::var("HOME").expect("$HOME not set, can't find cache folder");
+        format!("{}/.cache", home)
+    });
+
+    format!("{}/tudo/index.json", cache_home)
+}
+
+// In nanoseconds, a change made in the same second as the scan must still count
+fn mtime(path: &str) -> Option<u128> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+// Modification times of the directories and all their subdirectories. Adding or removing a
+// file changes the mtime of the directory holding it
+pub fn dir_mtimes(dirs: &[String]) -> HashMap<String, u128> {
+    let mut res = HashMap::new();
+    let mut pending = dirs.to_vec();
+
+    while let Some(dir) = pending.pop() {
+        let Some(modified) = mtime(&dir) else {
+            continue;
+        };
+        res.insert(dir.clone(), modified);
+
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    pending.push(entry.path().to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    res
+}
+
+impl Index {
+    pub fn load(path: impl AsRef<str>) -> Index {
+        let index = match fs::read(path.as_ref()) {
+            Ok(contents) => serde_json::from_slice::<Index>(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring unreadable index {}: {}", path.as_ref(), err);
+                Index::default()
+            }),
+            Err(_) => Index::default(),
+        };
+
+        Index {
+            sources: match index.version {
+                INDEX_VERSION => index.sources,
+                _ => HashMap::new(),
+            },
+            version: INDEX_VERSION,
+            path: Some(path.as_ref().to_string()),
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(err) = fs::write(path, serde_json::to_vec(&self).unwrap()) {
+            warn!("Could not save index to {}: {}", path, err);
+        }
+    }
+
+    // `mtimes` must be taken before generating the items, so changes made during the scan
+    // are picked up on the next start
+    pub fn update(&mut self, source: &str, mtimes: HashMap<String, u128>, items: &[SourceItem]) {
+        let Some(items) = items
+            .iter()
+            .map(CachedItem::from_item)
+            .collect::<Option<Vec<CachedItem>>>()
+        else {
+            warn!("Source {} has items that can't be cached", source);
+            return;
+        };
+
+        self.sources
+            .insert(source.to_string(), SourceIndex { items, mtimes });
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_roundtrip() {
+        let item = SourceItem::new(
+            "apps",
+            "Firefox",
+            Box::new(RunAction {
+                path: "firefox".to_string(),
+                exit_after: true,
+                clip_output: false,
+                label: Some("Launch".to_string()),
+            }),
+        )
+        .with_id("firefox.desktop")
+        .with_subtitle(Some("Browse the web".to_string()))
+        .with_action(Box::new(PassOtpAction {
+            secret_name: "mozilla".to_string(),
+            store: Some("/home/me/work-store".to_string()),
+        }));
+
+        let cached = CachedItem::from_item(&item).unwrap();
+        let json = serde_json::to_string(&cached).unwrap();
+        let restored = serde_json::from_str::<CachedItem>(&json)
+            .unwrap()
+            .into_item();
+
+        assert!(restored == item);
+        assert_eq!(restored.actions.len(), 2);
+        assert_eq!(restored.action().name(), "Launch");
+        // The store of a secret survives the restart too
+        assert_eq!(restored.actions[1].cached(), item.actions[1].cached());
+
+        // Icons removed since the index was written are dropped
+        let stale = CachedItem {
+            icon: Some("/nonexistent/firefox.png".to_string()),
+            ..cached
+        };
+        assert_eq!(stale.into_item().icon, None);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let dir = std::env::temp_dir().join(format!("tudo-index-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let dirs = vec![dir.to_string_lossy().to_string()];
+
+        let index = SourceIndex {
+            items: Vec::new(),
+            mtimes: dir_mtimes(&dirs),
+        };
+        assert_eq!(index.mtimes.len(), 2);
+        assert!(index.is_fresh(&dir_mtimes(&dirs)));
+
+        // Right after the snapshot, most likely within the same second
+        fs::write(dir.join("sub/new.desktop"), "").unwrap();
+        assert!(!index.is_fresh(&dir_mtimes(&dirs)));
+
+        fs::remove_dir_all(dir.join("sub")).unwrap();
+        assert!(!index.is_fresh(&dir_mtimes(&dirs)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use std::{thread::sleep, time::Duration};
 
-use crate::sources::{Source, SourceItem};
+use crate::sources::{ItemSink, Source, SourceError};
 
 pub struct DummySource {}
 
@@ -17,11 +17,14 @@ impl DummySource {
 }
 
 impl Source for DummySource {
+    fn name(&self) -> String {
+        "dummy".to_string()
+    }
     fn is_async(&self) -> bool {
         false
     }
-    fn generate_items(&self) -> Vec<SourceItem> {
+    fn generate_items(&self, _sink: &ItemSink) -> Result<(), SourceError> {
         sleep(Duration::new(3, 0));
-        vec![]
+        Ok(())
     }
 }
@@ -1,15 +1,39 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex, Weak},
+};
 
-use mlua::{Error, IntoLua, Lua, LuaSerdeExt, Table};
-use ureq::serde_json;
+use log::{debug, warn};
+use mlua::{Function, Lua, RegistryKey, Table, Value, Variadic};
 
-use crate::sources::Action;
+use crate::{sources::Action, App};
 
-use super::{actions::PassSecretAction, actions::RunAction, Source, SourceItem};
+use super::{
+    actions::PassSecretAction, actions::RunAction, lua_api, plugins::append_log, ItemSink,
+    RefreshPolicy, Source, SourceError, SourceItem,
+};
 
 pub struct LuaSource {
     pub items: Vec<SourceItem>,
     pub source: String,
+    pub name: Option<String>,
+    pub refresh_policy: RefreshPolicy,
+    // File the script's print output and errors go to, instead of stdout
+    pub log: Option<String>,
+    // Store of the secrets source, for `secret` actions that don't name one
+    pub password_store: Option<String>,
+    // State of the last successful run if the script defines `query`, which is called in it
+    state: Mutex<Option<Arc<Mutex<Lua>>>>,
+}
+
+// What items parsed from a script need in order to call back into it. The state is only
+// kept alive by the function actions of its items, so a new run doesn't leak the old one
+#[derive(Clone)]
+struct Script {
+    source: String,
+    lua: Weak<Mutex<Lua>>,
+    log: Option<String>,
+    password_store: Option<String>,
 }
 
 impl LuaSource {
@@ -17,111 +41,358 @@ impl LuaSource {
         LuaSource {
             items: Vec::new(),
             source,
+            name: None,
+            refresh_policy: RefreshPolicy::Static,
+            log: None,
+            password_store: None,
+            state: Mutex::new(None),
         }
     }
+    pub fn with_name(mut self, name: impl AsRef<str>) -> Self {
+        self.name = Some(name.as_ref().to_string());
+        self
+    }
+    pub fn with_refresh_policy(mut self, policy: RefreshPolicy) -> Self {
+        self.refresh_policy = policy;
+        self
+    }
+    pub fn with_log(mut self, path: impl AsRef<str>) -> Self {
+        self.log = Some(path.as_ref().to_string());
+        self
+    }
+    pub fn with_password_store(mut self, store: Option<String>) -> Self {
+        self.password_store = store;
+        self
+    }
+
+    // `emit` sends to whoever is asking for items right now
+    fn set_emit(lua: &Lua, script: &Script, sink: &ItemSink) -> Result<(), SourceError> {
+        let emit_script = script.clone();
+        let emit_sink = sink.clone();
+        let emit = lua.create_function(move |lua, items: Vec<Table>| {
+            let items = items
+                .iter()
+                .map(|item| parse_item(lua, &emit_script, item))
+                .collect::<Result<Vec<SourceItem>, SourceError>>()
+                .map_err(mlua::Error::external)?;
+            emit_sink.send(items);
+            Ok(())
+        })?;
+        let tudo: Table = lua.globals().get("tudo")?;
+        tudo.set("emit", emit.clone())?;
+        lua.globals().set("emit", emit)?;
+        Ok(())
+    }
+
+    // Items returned by the script, or by its `query` function
+    fn send_items(
+        lua: &Lua,
+        script: &Script,
+        sink: &ItemSink,
+        items: Option<Vec<Table>>,
+    ) -> Result<(), SourceError> {
+        let items = items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| parse_item(lua, script, item))
+            .collect::<Result<Vec<SourceItem>, SourceError>>()?;
+        sink.send(items);
+        Ok(())
+    }
+
+    fn run(&self, lua: &Lua, script: &Script, sink: &ItemSink) -> Result<(), SourceError> {
+        let contents = std::fs::read(&self.source)?;
+
+        Self::set_emit(lua, script, sink)?;
+        let res: Option<Vec<Table>> = lua.load(&contents).set_name(&self.source).eval()?;
+        Self::send_items(lua, script, sink, res)
+    }
+
+    // Only scripts with a `query` function need their state after the run
+    fn keep_state(&self, lua: &Lua, state: &Arc<Mutex<Lua>>) {
+        let query = lua.globals().get::<_, Option<Function>>("query");
+        *self.state.lock().unwrap() = match query {
+            Ok(Some(_)) => Some(state.clone()),
+            _ => None,
+        };
+    }
+
+    fn run_query(
+        &self,
+        state: &Arc<Mutex<Lua>>,
+        text: &str,
+        sink: &ItemSink,
+    ) -> Result<(), SourceError> {
+        let lua = state.lock().unwrap();
+        let Some(query) = lua.globals().get::<_, Option<Function>>("query")? else {
+            return Ok(());
+        };
+        let script = Script {
+            source: self.name(),
+            lua: Arc::downgrade(state),
+            log: self.log.clone(),
+            password_store: self.password_store.clone(),
+        };
+
+        Self::set_emit(&lua, &script, sink)?;
+        let res: Option<Vec<Table>> = query.call(text)?;
+        Self::send_items(&lua, &script, sink, res)
+    }
 }
 
-// Struct that wraps a serde_jso::Value and implements the IntoLua trait
-// FIXME(quadrado) only supports root objects
-struct LuaJSON {
-    value: serde_json::Value,
+// Calls a function returned by a script, e.g. action = function(ctx) ctx.copy("foo") end
+#[derive(Clone)]
+pub struct LuaFunctionAction {
+    script: Script,
+    lua: Arc<Mutex<Lua>>,
+    function: Arc<RegistryKey>,
+    label: Option<String>,
 }
-impl<'lua> IntoLua<'lua> for LuaJSON {
-    fn into_lua(self, lua: &'lua Lua) -> mlua::prelude::LuaResult<mlua::prelude::LuaValue<'lua>> {
-        match self.value {
-            serde_json::Value::Object(obj) => Ok(lua.to_value(&obj)?),
-            _ => panic!("Unsupported JSON return type"),
-        }
+
+impl LuaFunctionAction {
+    // The functions in `ctx` only live for the call
+    fn call(&self, lua: &Lua, app: &mut App) -> mlua::Result<()> {
+        let app = RefCell::new(app);
+        lua.scope(|scope| {
+            let ctx = lua.create_table()?;
+            ctx.set(
+                "copy",
+                scope.create_function(|_, text: String| {
+                    app.borrow_mut().clipboard = Some(text);
+                    Ok(())
+                })?,
+            )?;
+            ctx.set(
+                "hide",
+                scope.create_function(|_, ()| {
+                    app.borrow_mut().should_hide = true;
+                    Ok(())
+                })?,
+            )?;
+            ctx.set(
+                "set_prompt",
+                scope.create_function(|_, text: String| {
+                    app.borrow_mut().prompt = Some(text);
+                    Ok(())
+                })?,
+            )?;
+            // Shown in place of the list until Escape, like the actions menu
+            ctx.set(
+                "push",
+                scope.create_function(|lua, items: Vec<Table>| {
+                    let items = items
+                        .iter()
+                        .map(|item| parse_item(lua, &self.script, item))
+                        .collect::<Result<Vec<SourceItem>, SourceError>>()
+                        .map_err(mlua::Error::external)?;
+                    app.borrow_mut().pushed_items = Some(items);
+                    Ok(())
+                })?,
+            )?;
+
+            let function: Function = lua.registry_value(&self.function)?;
+            function.call::<_, ()>(ctx)
+        })
     }
 }
 
-fn http_get(
-    _lua: &Lua,
-    req_args: (String, HashMap<String, String>),
-) -> Result<impl IntoLua, Error> {
-    let mut req = ureq::get(&req_args.0);
+impl Action for LuaFunctionAction {
+    fn execute(&self, ctx: &mut App) {
+        // Items emitted early can be selected while the script is still going, and queries
+        // run in the same state. The window stays open with the spinner until it's free
+        let Ok(lua) = self.lua.try_lock() else {
+            debug!("{} is busy, waiting to run the action", self.script.source);
+            ctx.waiting_action = Some(Box::new(self.clone()));
+            return;
+        };
 
-    for (k, v) in req_args.1 {
-        req = req.set(&k, &v);
+        if let Err(err) = self.call(&lua, ctx) {
+            warn!("{}: {}", self.script.source, err);
+            if let Some(log) = &self.script.log {
+                append_log(log, &format!("error: {}", err));
+            }
+        }
     }
+    fn tags(&self) -> Vec<String> {
+        vec!["lua".to_string()]
+    }
+    fn name(&self) -> String {
+        self.label.clone().unwrap_or("Run".to_string())
+    }
+}
 
-    let contents: serde_json::Value = match req.call() {
-        Ok(response) => response.into_json().unwrap(),
-        Err(err) => return Err(mlua::Error::RuntimeError(err.to_string())),
-    };
-    Ok(LuaJSON { value: contents })
+fn function_action(
+    lua: &Lua,
+    script: &Script,
+    function: Function,
+    label: Option<String>,
+) -> Result<Box<dyn Action + Send>, SourceError> {
+    let state = script
+        .lua
+        .upgrade()
+        .ok_or(SourceError::Failed("script state is gone".to_string()))?;
+
+    Ok(Box::new(LuaFunctionAction {
+        script: script.clone(),
+        lua: state,
+        function: Arc::new(lua.create_registry_value(function)?),
+        label,
+    }))
 }
 
-fn load_json_file(_lua: &Lua, path: String) -> Result<impl IntoLua, Error> {
-    let res = std::fs::read(path).unwrap();
+// Either a function, or a table with the action type and its fields
+fn parse_action(
+    lua: &Lua,
+    script: &Script,
+    action: Value,
+) -> Result<Box<dyn Action + Send>, SourceError> {
+    let action_table = match action {
+        Value::Function(function) => return function_action(lua, script, function, None),
+        Value::Table(table) => table,
+        _ => {
+            return Err(SourceError::Failed(
+                "action must be a table or a function".to_string(),
+            ))
+        }
+    };
+    let action_type: String = action_table.get("type")?;
 
-    let contents = serde_json::from_slice(res.as_slice()).unwrap();
-    Ok(LuaJSON { value: contents })
+    Ok(match action_type.as_str() {
+        "run" => Box::new(RunAction {
+            path: action_table.get("path")?,
+            clip_output: false,
+            exit_after: true,
+            label: action_table.get("label")?,
+        }),
+        "secret" => Box::new(PassSecretAction {
+            secret_name: action_table.get("secret_name")?,
+            store: action_table
+                .get::<_, Option<String>>("store")?
+                .or_else(|| script.password_store.clone()),
+        }),
+        "function" => {
+            return function_action(
+                lua,
+                script,
+                action_table.get("call")?,
+                action_table.get("label")?,
+            )
+        }
+        _ => {
+            return Err(SourceError::Failed(format!(
+                "unsupported action type '{}'",
+                action_type
+            )))
+        }
+    })
 }
 
-// Set some utility functions
-fn setup(lua: &Lua) {
-    let http_get = lua.create_function(http_get).unwrap();
-    let load_json_file = lua.create_function(load_json_file).unwrap();
+fn parse_item(lua: &Lua, script: &Script, v: &Table) -> Result<SourceItem, SourceError> {
+    let title: String = v.get("title".to_string())?;
+    let icon: Option<String> = v.get("icon")?;
+    let id: Option<String> = v.get("id")?;
+    let subtitle: Option<String> = v.get("subtitle")?;
+    let keywords: Option<Vec<String>> = v.get("keywords")?;
+    let score_boost: Option<i32> = v.get("score_boost")?;
+    let action: Value = v.get("action")?;
+    // Secondary actions, offered in the actions menu
+    let extra_actions: Option<Vec<Value>> = v.get("actions")?;
 
-    lua.globals().set("http_get", http_get).unwrap();
-    lua.globals().set("open_json", load_json_file).unwrap();
+    let action = parse_action(lua, script, action)?;
+    let mut item = SourceItem::new(&script.source, title, action)
+        .with_icon(icon)
+        .with_subtitle(subtitle)
+        .with_keywords(keywords.unwrap_or_default())
+        .with_score_boost(score_boost.unwrap_or(0));
+    if let Some(id) = id {
+        item = item.with_id(id);
+    }
+    for action in extra_actions.unwrap_or_default() {
+        item = item.with_action(parse_action(lua, script, action)?);
+    }
+    Ok(item)
 }
 
 impl Source for LuaSource {
+    // Named after the script unless told otherwise, e.g. "vlad" for plugins/vlad.lua
+    fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        std::path::Path::new(&self.source)
+            .file_stem()
+            .map_or("lua".to_string(), |stem| stem.to_string_lossy().to_string())
+    }
+
+    fn refresh_policy(&self) -> RefreshPolicy {
+        self.refresh_policy
+    }
+
     fn is_async(&self) -> bool {
         false
     }
 
-    fn generate_items(&self) -> Vec<SourceItem> {
-        let mut items = Vec::<SourceItem>::new();
-
+    // Scripts either return their items, or hand them over as they get them with
+    // `tudo.emit(items)`, e.g. after each page of an HTTP API
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
         // Rewrite this with serde!!
-        let lua = Lua::new();
-        let res: Vec<Table>;
-
-        let _script = std::fs::read(&self.source);
-        let script;
-        if _script.is_err() {
-            // Return empty string if error reading script
-            return vec![];
-        } else {
-            script = _script.unwrap();
-        }
+        let state = Arc::new(Mutex::new(Lua::new()));
+        let lua = state.lock().unwrap();
+        let script = Script {
+            source: self.name(),
+            lua: Arc::downgrade(&state),
+            log: self.log.clone(),
+            password_store: self.password_store.clone(),
+        };
 
-        setup(&lua);
+        let tudo = lua_api::register(&lua, &script.source, self.log.clone())?;
+        tudo.set("password_store", self.password_store.clone())?;
 
-        res = match lua.load(&script).set_name("teste").eval() {
-            Ok(r) => r,
-            Err(err) => {
-                println!("{}", err);
-                return vec![];
+        let Some(log) = self.log.clone() else {
+            let res = self.run(&lua, &script, sink);
+            if res.is_ok() {
+                self.keep_state(&lua, &state);
             }
+            return res;
+        };
+
+        // Each run starts a fresh log
+        let _ = std::fs::remove_file(&log);
+        let print_log = log.clone();
+        let print = lua.create_function(move |lua, args: Variadic<Value>| {
+            let tostring: Function = lua.globals().get("tostring")?;
+            let line = args
+                .into_iter()
+                .map(|arg| tostring.call::<_, String>(arg))
+                .collect::<mlua::Result<Vec<String>>>()?;
+            append_log(&print_log, &line.join("\t"));
+            Ok(())
+        })?;
+        lua.globals().set("print", print)?;
+
+        let res = self.run(&lua, &script, sink);
+        match &res {
+            Ok(_) => self.keep_state(&lua, &state),
+            Err(err) => append_log(&log, &format!("error: {}", err)),
+        }
+        res
+    }
+
+    // Scripts that define a global `query(text)` function get called with the prompt text,
+    // and answer with items the same way the script does. Only known once the script ran
+    fn is_dynamic(&self) -> bool {
+        self.state.lock().unwrap().is_some()
+    }
+
+    fn query(&self, text: &str, sink: &ItemSink) -> Result<(), SourceError> {
+        let Some(state) = self.state.lock().unwrap().clone() else {
+            return Ok(());
         };
 
-        for v in res.iter() {
-            let title: String = v.get("title".to_string()).unwrap();
-            let icon: Option<String> = v.get("icon").unwrap();
-            let action_table: Table = v.get("action").unwrap();
-            let action_type: String = action_table.get("type").unwrap();
-
-            let action: Box<dyn Action + Send> = match action_type.as_str() {
-                "run" => Box::new(RunAction {
-                    path: action_table.get("path").unwrap(),
-                    clip_output: false,
-                    exit_after: true,
-                }),
-                "secret" => Box::new(PassSecretAction {
-                    secret_name: action_table.get("secret_name").unwrap(),
-                }),
-                _ => panic!("Unsupported lua action type"),
-            };
-            items.push(SourceItem {
-                title: title.clone(),
-                icon: icon.clone(),
-                action: action.clone(),
-            });
+        let res = self.run_query(&state, text, sink);
+        if let (Err(err), Some(log)) = (&res, &self.log) {
+            append_log(log, &format!("query error: {}", err));
         }
-        items
+        res
     }
 }
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    fs,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use log::{info, warn};
+use mlua::{Error, IntoLua, Lua, LuaSerdeExt, Table, Value};
+use ureq::serde_json;
+
+use crate::utils::{clipboard::set_clipboard, xdg::IconFinder};
+
+use super::plugins::append_log;
+
+// Loading the icon themes takes a while, every script shares the same finder
+static ICON_FINDER: OnceLock<IconFinder> = OnceLock::new();
+
+// Struct that wraps a serde_jso::Value and implements the IntoLua trait
+struct LuaJSON {
+    value: serde_json::Value,
+}
+impl<'lua> IntoLua<'lua> for LuaJSON {
+    fn into_lua(self, lua: &'lua Lua) -> mlua::prelude::LuaResult<mlua::prelude::LuaValue<'lua>> {
+        lua.to_value(&self.value)
+    }
+}
+
+fn http_get(
+    _lua: &Lua,
+    req_args: (String, HashMap<String, String>),
+) -> Result<impl IntoLua<'_>, Error> {
+    let mut req = ureq::get(&req_args.0);
+
+    for (k, v) in req_args.1 {
+        req = req.set(&k, &v);
+    }
+
+    let contents: serde_json::Value = match req.call() {
+        Ok(response) => response.into_json().map_err(Error::external)?,
+        Err(err) => return Err(mlua::Error::RuntimeError(err.to_string())),
+    };
+    Ok(LuaJSON { value: contents })
+}
+
+fn load_json_file(_lua: &Lua, path: String) -> Result<impl IntoLua<'_>, Error> {
+    let res = fs::read(path).map_err(Error::external)?;
+
+    let contents = serde_json::from_slice(res.as_slice()).map_err(Error::external)?;
+    Ok(LuaJSON { value: contents })
+}
+
+// A string goes through `sh -c`, a table is the program and its arguments
+fn run<'lua>(lua: &'lua Lua, cmd: Value<'lua>) -> Result<Table<'lua>, Error> {
+    let mut command = match cmd {
+        Value::String(cmd) => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(cmd.to_str()?);
+            command
+        }
+        Value::Table(args) => {
+            let args: Vec<String> = lua.from_value(Value::Table(args))?;
+            let Some((program, args)) = args.split_first() else {
+                return Err(Error::RuntimeError("run: empty command".to_string()));
+            };
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+        _ => {
+            return Err(Error::RuntimeError(
+                "run: expected a string or a table".to_string(),
+            ))
+        }
+    };
+
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(Error::external)?;
+    let res = lua.create_table()?;
+    res.set("code", output.status.code())?;
+    res.set("stdout", String::from_utf8_lossy(&output.stdout))?;
+    res.set("stderr", String::from_utf8_lossy(&output.stderr))?;
+    Ok(res)
+}
+
+// Entry names, sorted
+fn read_dir(_lua: &Lua, path: String) -> Result<Vec<String>, Error> {
+    let mut names = fs::read_dir(path)
+        .map_err(Error::external)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    names.sort();
+    Ok(names)
+}
+
+fn notify(_lua: &Lua, (summary, body): (String, Option<String>)) -> Result<(), Error> {
+    let mut command = Command::new("notify-send");
+    command.arg(summary);
+    if let Some(body) = body {
+        command.arg(body);
+    }
+    command.spawn().map_err(Error::external)?;
+    Ok(())
+}
+
+fn icon_lookup(_lua: &Lua, (name, size): (String, Option<u32>)) -> Result<Option<String>, Error> {
+    let finder = ICON_FINDER.get_or_init(IconFinder::new);
+    Ok(finder.get_icon_with_size(name, size.unwrap_or(32)))
+}
+
+fn json_encode(lua: &Lua, value: Value) -> Result<String, Error> {
+    let value: serde_json::Value = lua.from_value(value)?;
+    serde_json::to_string(&value).map_err(Error::external)
+}
+
+fn json_decode(_lua: &Lua, text: String) -> Result<impl IntoLua<'_>, Error> {
+    let value = serde_json::from_str(&text).map_err(Error::external)?;
+    Ok(LuaJSON { value })
+}
+
+// Messages go to the application log, and to the script's own log when it has one
+fn log_table<'lua>(
+    lua: &'lua Lua,
+    source: &str,
+    log: Option<String>,
+) -> Result<Table<'lua>, Error> {
+    let table = lua.create_table()?;
+
+    let (info_source, info_log) = (source.to_string(), log.clone());
+    table.set(
+        "info",
+        lua.create_function(move |_, message: String| {
+            info!("{}: {}", info_source, message);
+            if let Some(log) = &info_log {
+                append_log(log, &format!("info: {}", message));
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let warn_source = source.to_string();
+    table.set(
+        "warn",
+        lua.create_function(move |_, message: String| {
+            warn!("{}: {}", warn_source, message);
+            if let Some(log) = &log {
+                append_log(log, &format!("warn: {}", message));
+            }
+            Ok(())
+        })?,
+    )?;
+    Ok(table)
+}
+
+// The `tudo` module every script gets, plus the older `http_get` and `open_json` globals
+pub fn register<'lua>(
+    lua: &'lua Lua,
+    source: &str,
+    log: Option<String>,
+) -> Result<Table<'lua>, Error> {
+    let globals = lua.globals();
+    globals.set("http_get", lua.create_function(http_get)?)?;
+    globals.set("open_json", lua.create_function(load_json_file)?)?;
+
+    let tudo = lua.create_table()?;
+    tudo.set("run", lua.create_function(run)?)?;
+    tudo.set(
+        "read_file",
+        lua.create_function(|_, path: String| fs::read_to_string(path).map_err(Error::external))?,
+    )?;
+    tudo.set("read_dir", lua.create_function(read_dir)?)?;
+    tudo.set(
+        "env",
+        lua.create_function(|_, name: String| Ok(std::env::var(name).ok()))?,
+    )?;
+    tudo.set("notify", lua.create_function(notify)?)?;
+    tudo.set("icon_lookup", lua.create_function(icon_lookup)?)?;
+    tudo.set("http_get", lua.create_function(http_get)?)?;
+
+    let clipboard = lua.create_table()?;
+    clipboard.set(
+        "set",
+        lua.create_function(|_, text: String| set_clipboard(&text).map_err(Error::external))?,
+    )?;
+    tudo.set("clipboard", clipboard)?;
+
+    let json = lua.create_table()?;
+    json.set("encode", lua.create_function(json_encode)?)?;
+    json.set("decode", lua.create_function(json_decode)?)?;
+    json.set("read", lua.create_function(load_json_file)?)?;
+    tudo.set("json", json)?;
+
+    tudo.set("log", log_table(lua, source, log)?)?;
+
+    globals.set("tudo", tudo.clone())?;
+    Ok(tudo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_api() {
+        let lua = Lua::new();
+        register(&lua, "test", None).unwrap();
+
+        let (stdout, code): (String, i32) = lua
+            .load("local res = tudo.run({ 'echo', 'hi' }) return res.stdout, res.code")
+            .eval()
+            .unwrap();
+        assert_eq!((stdout.as_str(), code), ("hi\n", 0));
+
+        // JSON goes both ways, not only for objects
+        let (encoded, second): (String, i32) = lua
+            .load("return tudo.json.encode({ a = 1 }), tudo.json.decode('[1, 2, 3]')[2]")
+            .eval()
+            .unwrap();
+        assert_eq!((encoded.as_str(), second), ("{\"a\":1}", 2));
+
+        let missing: Option<String> = lua
+            .load("return tudo.env('TUDO_SURELY_NOT_SET')")
+            .eval()
+            .unwrap();
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,838 @@
+use std::{
+    collections::{HashMap, HashSet},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use super::{
+    cache::{self, dir_mtimes, Index},
+    watcher::Watcher,
+    ItemSink, RefreshPolicy, Source, SourceEntry, SourceError, SourceEvent, SourceItem,
+};
+
+type SharedSource = Arc<Mutex<Box<dyn Source + Send>>>;
+
+// How long the prompt has to stay the same before dynamic sources are queried
+const QUERY_DEBOUNCE: Duration = Duration::from_millis(150);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Query answers are kept apart from the regular items of the source
+fn query_source(name: &str) -> String {
+    format!("{}?query", name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceStatus {
+    Loading,
+    Ready { items: usize, elapsed: Duration },
+    // The items from the last successful run, if any, are kept
+    Failed(SourceError),
+}
+
+// Status of every source by name, in the order they were given
+pub type SourceStatuses = Arc<Mutex<Vec<(String, SourceStatus)>>>;
+
+fn set_status(statuses: &SourceStatuses, source: &str, status: SourceStatus) {
+    let mut statuses = statuses.lock().unwrap();
+    match statuses.iter_mut().find(|(name, _)| name == source) {
+        Some(entry) => entry.1 = status,
+        None => statuses.push((source.to_string(), status)),
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// Run generate_items on its own thread so a source that hangs or panics can't take the
+// caller down with it. Batches are handed to `forward` as they arrive, and after a timeout
+// whatever the source still sends is dropped
+fn generate(
+    name: &str,
+    source: SharedSource,
+    timeout: Duration,
+    forward: impl FnMut(Vec<SourceItem>),
+) -> Result<usize, SourceError> {
+    guarded(
+        name,
+        source,
+        timeout,
+        |source, sink| source.generate_items(sink),
+        forward,
+    )
+}
+
+// Same as `generate`, for any work that sends items through a sink
+fn guarded(
+    name: &str,
+    source: SharedSource,
+    timeout: Duration,
+    work: impl FnOnce(&(dyn Source + Send), &ItemSink) -> Result<(), SourceError> + Send + 'static,
+    mut forward: impl FnMut(Vec<SourceItem>),
+) -> Result<usize, SourceError> {
+    let (tx, rx) = channel::<SourceEvent>();
+    let sink = ItemSink::new(name, tx.clone());
+
+    thread::spawn(move || {
+        let source = source
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let source_name = source.name();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| work(source.as_ref(), &sink)))
+            .unwrap_or_else(|payload| Err(SourceError::Panicked(panic_message(payload))));
+        let _ = tx.send(SourceEvent::Done {
+            source: source_name,
+            result: result.map(|_| 0),
+        });
+    });
+
+    // No deadline for sources that can take forever
+    let deadline = Instant::now().checked_add(timeout);
+    let mut count = 0;
+    loop {
+        let event = match deadline {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match event {
+            Ok(SourceEvent::Batch { items, .. }) => {
+                count += items.len();
+                forward(items);
+            }
+            Ok(SourceEvent::Done { result, .. }) => return result.map(|_| count),
+            Err(RecvTimeoutError::Timeout) => return Err(SourceError::Timeout(timeout)),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(SourceError::Failed("stopped without finishing".to_string()))
+            }
+        }
+    }
+}
+
+struct ManagedSource {
+    // Kept apart so looking a source up never waits on a running scan
+    name: String,
+    prefix: Option<String>,
+    policy: RefreshPolicy,
+    // Whether it answers queries, checked again after every successful run since scripts
+    // only tell once they ran
+    dynamic: Arc<AtomicBool>,
+    source: SharedSource,
+    last_run: Option<Instant>,
+    // Asked to run while it was busy, it runs once it isn't
+    pending: bool,
+}
+
+impl ManagedSource {
+    // Some thread still has the source: a run, a query, or one that timed out and may never
+    // give it back
+    fn busy(&self) -> bool {
+        Arc::strong_count(&self.source) > 1
+    }
+}
+
+// A run in progress, until its Done event comes in
+struct Run {
+    id: u64,
+    // Only loads and reloads count, refreshes happen quietly in the background
+    track: bool,
+    // The source had nothing in the list when the run started, so batches are shown as they
+    // come. Otherwise the previous items stay until the run is done
+    stream: bool,
+    items: Vec<SourceItem>,
+    started: Instant,
+}
+
+// Events are tagged with the run they belong to, so late events of a run that timed out
+// or was superseded are ignored
+struct RunEvent {
+    run: u64,
+    event: SourceEvent,
+}
+
+// Answer of a dynamic source to the query with the given id, older answers are ignored
+struct QueryEvent {
+    query: u64,
+    source: String,
+    result: Result<Vec<SourceItem>, SourceError>,
+}
+
+// Runs the sources on worker threads and keeps the shared item list up to date.
+// Sources with watched directories are served from the on-disk index when `fast_start` is set,
+// and refreshed whenever something changes in those directories
+pub struct SourceManager {
+    pub items: Arc<Mutex<Vec<SourceItem>>>,
+    pub statuses: SourceStatuses,
+    sources: Vec<ManagedSource>,
+    runs: HashMap<String, Run>,
+    next_run: u64,
+    tx: Sender<RunEvent>,
+    rx: Receiver<RunEvent>,
+    index: Arc<Mutex<Index>>,
+    watcher: Option<Watcher>,
+    fast_start: bool,
+    // Prompt text, and when it last changed if dynamic sources haven't been asked about it yet
+    query: String,
+    query_changed: Option<Instant>,
+    query_id: Arc<AtomicU64>,
+    query_tx: Sender<QueryEvent>,
+    query_rx: Receiver<QueryEvent>,
+}
+
+// Swap the items of a single source, the other sources stay untouched
+fn replace_items(items: &Arc<Mutex<Vec<SourceItem>>>, source: &str, new_items: Vec<SourceItem>) {
+    let mut items = items.lock().unwrap();
+    items.retain(|item| item.source != source);
+    items.extend(new_items);
+}
+
+impl SourceManager {
+    pub fn new(fast_start: bool) -> SourceManager {
+        let (tx, rx) = channel::<RunEvent>();
+        let (query_tx, query_rx) = channel::<QueryEvent>();
+
+        SourceManager {
+            items: Arc::new(Mutex::new(Vec::new())),
+            statuses: Arc::new(Mutex::new(Vec::new())),
+            sources: Vec::new(),
+            runs: HashMap::new(),
+            next_run: 0,
+            tx,
+            rx,
+            index: Arc::new(Mutex::new(Index::load(cache::default_path()))),
+            watcher: None,
+            fast_start,
+            query: String::new(),
+            query_changed: None,
+            query_id: Arc::new(AtomicU64::new(0)),
+            query_tx,
+            query_rx,
+        }
+    }
+
+    pub fn loading(&self) -> bool {
+        self.runs.values().any(|run| run.track)
+    }
+
+    // Fill the list, using the index when possible
+    pub fn load(&mut self, sources: Vec<SourceEntry>) {
+        self.set_sources(sources);
+        self.run_all(self.fast_start);
+    }
+
+    // Rescan every source, ignoring the index
+    pub fn reload(&mut self, sources: Vec<SourceEntry>) {
+        self.set_sources(sources);
+        self.run_all(false);
+    }
+
+    // The window was just shown
+    pub fn on_show(&mut self) {
+        for idx in 0..self.sources.len() {
+            if self.sources[idx].policy == RefreshPolicy::OnShow {
+                self.spawn(idx, false, false);
+            }
+        }
+    }
+
+    // Take in what the sources sent since the last call, then refresh the sources whose
+    // directories changed or whose interval elapsed
+    pub fn poll(&mut self) {
+        while let Ok(RunEvent { run, event }) = self.rx.try_recv() {
+            self.handle(run, event);
+        }
+        self.poll_query();
+
+        let mut changed: HashSet<String> = HashSet::new();
+
+        if let Some(watcher) = &self.watcher {
+            for change in watcher.changes() {
+                let Some(managed) = self.sources.iter().find(|s| s.name == change.source) else {
+                    continue;
+                };
+                // A source busy scanning will be refreshed anyway
+                let needs_refresh = managed
+                    .source
+                    .try_lock()
+                    .map_or(true, |source| source.needs_refresh(&change.path));
+                if needs_refresh {
+                    info!("Refreshing {} after changes on disk", change.source);
+                    changed.insert(change.source);
+                }
+            }
+        }
+
+        for managed in self.sources.iter() {
+            if let (RefreshPolicy::Interval(interval), Some(last_run)) =
+                (managed.policy, managed.last_run)
+            {
+                if last_run.elapsed() >= interval {
+                    changed.insert(managed.name.clone());
+                }
+            }
+        }
+
+        for name in changed {
+            self.refresh(&name);
+        }
+
+        for idx in 0..self.sources.len() {
+            if self.sources[idx].pending && !self.sources[idx].busy() {
+                self.spawn(idx, false, false);
+            }
+        }
+    }
+
+    // Dynamic sources are asked about `text` once it stops changing for a moment
+    pub fn set_query(&mut self, text: &str) {
+        if self.query != text {
+            self.query = text.to_string();
+            self.query_changed = Some(Instant::now());
+        }
+    }
+
+    fn poll_query(&mut self) {
+        while let Ok(QueryEvent {
+            query,
+            source,
+            result,
+        }) = self.query_rx.try_recv()
+        {
+            if query != self.query_id.load(Ordering::SeqCst) {
+                continue;
+            }
+            match result {
+                Ok(items) => {
+                    let items = self
+                        .with_prefix(&source, items)
+                        .into_iter()
+                        .map(|mut item| {
+                            item.source = query_source(&source);
+                            item.from_query = true;
+                            item.run = query;
+                            item
+                        })
+                        .collect();
+                    replace_items(&self.items, &query_source(&source), items);
+                }
+                Err(err) => warn!("Source {} query {}", source, err),
+            }
+        }
+
+        match self.query_changed {
+            Some(changed) if changed.elapsed() >= QUERY_DEBOUNCE => (),
+            _ => return,
+        }
+        self.query_changed = None;
+        let id = self.query_id.fetch_add(1, Ordering::SeqCst) + 1;
+
+        for managed in self
+            .sources
+            .iter()
+            .filter(|s| s.dynamic.load(Ordering::SeqCst))
+        {
+            // Nothing typed, nothing to answer
+            if self.query.is_empty() {
+                replace_items(&self.items, &query_source(&managed.name), Vec::new());
+                continue;
+            }
+
+            let name = managed.name.clone();
+            let source = managed.source.clone();
+            let text = self.query.clone();
+            let current = self.query_id.clone();
+            let tx = self.query_tx.clone();
+            thread::spawn(move || {
+                // Waiting on a busy source may take a while, by then there may be a newer query
+                let work_current = current.clone();
+                let mut items = Vec::new();
+                let result = guarded(
+                    &name,
+                    source,
+                    QUERY_TIMEOUT,
+                    move |source, sink| match work_current.load(Ordering::SeqCst) == id {
+                        true => source.query(&text, sink),
+                        false => Ok(()),
+                    },
+                    |batch| items.extend(batch),
+                );
+                if current.load(Ordering::SeqCst) == id {
+                    let _ = tx.send(QueryEvent {
+                        query: id,
+                        source: name,
+                        result: result.map(|_| items),
+                    });
+                }
+            });
+        }
+    }
+
+    pub fn refresh(&mut self, name: &str) {
+        if let Some(idx) = self.sources.iter().position(|s| s.name == name) {
+            self.spawn(idx, false, false);
+        }
+    }
+
+    fn handle(&mut self, run: u64, event: SourceEvent) {
+        match event {
+            SourceEvent::Batch { source, items } => {
+                let mut items = self.with_prefix(&source, items);
+                for item in items.iter_mut() {
+                    item.run = run;
+                }
+                let Some(current) = self.runs.get_mut(&source).filter(|r| r.id == run) else {
+                    return;
+                };
+                if current.stream {
+                    self.items.lock().unwrap().extend(items.iter().cloned());
+                }
+                current.items.extend(items);
+            }
+            SourceEvent::Done { source, result } => {
+                if self.runs.get(&source).map(|r| r.id) != Some(run) {
+                    return;
+                }
+                let current = self.runs.remove(&source).unwrap();
+
+                match result {
+                    Ok(count) => {
+                        // It may answer queries now, ask it about what is already typed
+                        let dynamic = self
+                            .sources
+                            .iter()
+                            .any(|s| s.name == source && s.dynamic.load(Ordering::SeqCst));
+                        if dynamic {
+                            self.query_changed = Some(Instant::now());
+                        }
+                        set_status(
+                            &self.statuses,
+                            &source,
+                            SourceStatus::Ready {
+                                items: count,
+                                elapsed: current.started.elapsed(),
+                            },
+                        );
+                        replace_items(&self.items, &source, current.items);
+                    }
+                    // Whatever was already shown stays
+                    Err(err) => {
+                        warn!("Source {} {}", source, err);
+                        set_status(&self.statuses, &source, SourceStatus::Failed(err));
+                    }
+                }
+            }
+        }
+    }
+
+    // Stamp the configured prefix on items of `source`
+    fn with_prefix(&self, source: &str, items: Vec<SourceItem>) -> Vec<SourceItem> {
+        let prefix = self
+            .sources
+            .iter()
+            .find(|s| s.name == source)
+            .and_then(|s| s.prefix.clone());
+
+        items
+            .into_iter()
+            .map(|item| item.with_prefix(prefix.clone()))
+            .collect()
+    }
+
+    fn set_sources(&mut self, sources: Vec<SourceEntry>) {
+        // Anything still running belongs to the old sources
+        self.runs.clear();
+        self.sources = sources
+            .into_iter()
+            .map(|entry| ManagedSource {
+                name: entry.source.name(),
+                prefix: entry.prefix,
+                policy: entry.source.refresh_policy(),
+                dynamic: Arc::new(AtomicBool::new(entry.source.is_dynamic())),
+                source: Arc::new(Mutex::new(entry.source)),
+                last_run: None,
+                pending: false,
+            })
+            .collect();
+
+        // Items of sources that were disabled go away, answers to the query are asked for again
+        self.items
+            .lock()
+            .unwrap()
+            .retain(|item| !item.from_query && self.sources.iter().any(|s| s.name == item.source));
+        self.query_changed = Some(Instant::now());
+
+        *self.statuses.lock().unwrap() = self
+            .sources
+            .iter()
+            .map(|s| (s.name.clone(), SourceStatus::Loading))
+            .collect();
+
+        let dirs = self
+            .sources
+            .iter()
+            .map(|s| (s.name.clone(), s.source.lock().unwrap().watched_dirs()))
+            .filter(|(_, dirs)| !dirs.is_empty())
+            .collect::<Vec<(String, Vec<String>)>>();
+
+        self.watcher = match dirs.is_empty() {
+            true => None,
+            false => Watcher::new(dirs)
+                .inspect_err(|err| warn!("Sources won't refresh on changes: {}", err))
+                .ok(),
+        };
+    }
+
+    fn run_all(&mut self, use_index: bool) {
+        for idx in 0..self.sources.len() {
+            let name = self.sources[idx].name.clone();
+
+            // Show what we had last time right away, even if it is stale. Whether it is gets
+            // checked on the refresh thread, walking the directories takes a while
+            let indexed = use_index && {
+                let index = self.index.lock().unwrap();
+                match index.sources.get(&name) {
+                    Some(source_index) => {
+                        let cached = source_index
+                            .items
+                            .iter()
+                            .cloned()
+                            .map(|item| item.into_item())
+                            .collect();
+                        replace_items(&self.items, &name, self.with_prefix(&name, cached));
+                        true
+                    }
+                    None => false,
+                }
+            };
+
+            self.spawn(idx, true, indexed);
+        }
+    }
+
+    // With `use_index` the scan is skipped when the index is still fresh
+    fn spawn(&mut self, idx: usize, track: bool, use_index: bool) {
+        let managed = &mut self.sources[idx];
+        // Waiting for the source lock on a new thread would leak one thread per refresh of a
+        // source that hangs
+        if managed.busy() {
+            managed.pending = true;
+            return;
+        }
+        managed.pending = false;
+        managed.last_run = Some(Instant::now());
+
+        let name = managed.name.clone();
+        let id = self.next_run;
+        self.next_run += 1;
+
+        let stream = !self.items.lock().unwrap().iter().any(|i| i.source == name);
+        // A refresh doesn't make a pending load stop counting
+        let track = track || self.runs.get(&name).is_some_and(|r| r.track);
+        self.runs.insert(
+            name.clone(),
+            Run {
+                id,
+                track,
+                stream,
+                items: Vec::new(),
+                started: Instant::now(),
+            },
+        );
+
+        // 'async' it
+        let source = managed.source.clone();
+        let dynamic = managed.dynamic.clone();
+        let tx = self.tx.clone();
+        let index = self.index.clone();
+        thread::spawn(move || {
+            // At most a query still holding the lock, two scans of the same source never race
+            // each other
+            let (dirs, timeout) = {
+                let source = source
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                (source.watched_dirs(), source.timeout())
+            };
+            let mtimes = dir_mtimes(&dirs);
+
+            // Nothing changed since the index was written, its items are the result
+            let fresh = match use_index {
+                true => index
+                    .lock()
+                    .unwrap()
+                    .sources
+                    .get(&name)
+                    .filter(|source_index| source_index.is_fresh(&mtimes))
+                    .map(|source_index| source_index.items.clone()),
+                false => None,
+            };
+            if let Some(cached) = fresh {
+                info!("Loaded {} from index", name);
+                let items: Vec<SourceItem> = cached.into_iter().map(|i| i.into_item()).collect();
+                let count = items.len();
+                for event in [
+                    SourceEvent::Batch {
+                        source: name.clone(),
+                        items,
+                    },
+                    SourceEvent::Done {
+                        source: name,
+                        result: Ok(count),
+                    },
+                ] {
+                    let _ = tx.send(RunEvent { run: id, event });
+                }
+                return;
+            }
+
+            // Indexed sources need the whole list once done
+            let mut all_items = Vec::new();
+            let result = generate(&name, source.clone(), timeout, |items| {
+                if !dirs.is_empty() {
+                    all_items.extend(items.iter().cloned());
+                }
+                let _ = tx.send(RunEvent {
+                    run: id,
+                    event: SourceEvent::Batch {
+                        source: name.clone(),
+                        items,
+                    },
+                });
+            });
+
+            if result.is_ok() && !dirs.is_empty() {
+                index.lock().unwrap().update(&name, mtimes, &all_items);
+            }
+            // The run is over, so this only waits for the worker to let go of the source
+            if result.is_ok() {
+                let source = source
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                dynamic.store(source.is_dynamic(), Ordering::SeqCst);
+            }
+            // Let go before reporting, it isn't busy anymore once Done arrives
+            drop(source);
+            let _ = tx.send(RunEvent {
+                run: id,
+                event: SourceEvent::Done {
+                    source: name,
+                    result,
+                },
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{actions::PrintAction, DEFAULT_TIMEOUT};
+    use super::*;
+
+    // Sends one item, then takes its time and panics
+    struct Broken {
+        delay: Duration,
+    }
+
+    impl Source for Broken {
+        fn name(&self) -> String {
+            "broken".to_string()
+        }
+        fn is_async(&self) -> bool {
+            false
+        }
+        fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
+            sink.send(vec![SourceItem::new(
+                self.name(),
+                "first",
+                Box::new(PrintAction {
+                    text: "first".to_string(),
+                }),
+            )]);
+            thread::sleep(self.delay);
+            panic!("oops");
+        }
+    }
+
+    // Answers every query with an item titled after it
+    struct Echo;
+
+    impl Source for Echo {
+        fn name(&self) -> String {
+            "echo".to_string()
+        }
+        fn is_async(&self) -> bool {
+            false
+        }
+        fn generate_items(&self, _sink: &ItemSink) -> Result<(), SourceError> {
+            Ok(())
+        }
+        fn is_dynamic(&self) -> bool {
+            true
+        }
+        fn query(&self, text: &str, sink: &ItemSink) -> Result<(), SourceError> {
+            sink.send(vec![SourceItem::new(
+                self.name(),
+                text,
+                Box::new(PrintAction {
+                    text: text.to_string(),
+                }),
+            )]);
+            Ok(())
+        }
+    }
+
+    // Never done in time
+    struct Hang;
+
+    impl Source for Hang {
+        fn name(&self) -> String {
+            "hang".to_string()
+        }
+        fn is_async(&self) -> bool {
+            false
+        }
+        fn timeout(&self) -> Duration {
+            Duration::from_millis(20)
+        }
+        fn generate_items(&self, _sink: &ItemSink) -> Result<(), SourceError> {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        }
+    }
+
+    // The same item on every run
+    struct Same;
+
+    impl Source for Same {
+        fn name(&self) -> String {
+            "same".to_string()
+        }
+        fn is_async(&self) -> bool {
+            false
+        }
+        fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
+            sink.send(vec![SourceItem::new(
+                self.name(),
+                "same",
+                Box::new(PrintAction {
+                    text: "same".to_string(),
+                }),
+            )]);
+            Ok(())
+        }
+    }
+
+    fn wait_runs(manager: &mut SourceManager) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !manager.runs.is_empty() && Instant::now() < deadline {
+            manager.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn shared(delay: Duration) -> SharedSource {
+        Arc::new(Mutex::new(Box::new(Broken { delay })))
+    }
+
+    #[test]
+    fn test_generate_failures() {
+        let mut received = Vec::new();
+        let result = generate("broken", shared(Duration::ZERO), DEFAULT_TIMEOUT, |items| {
+            received.extend(items)
+        });
+        assert_eq!(result, Err(SourceError::Panicked("oops".to_string())));
+        // What was sent before failing still gets through
+        assert_eq!(received.len(), 1);
+
+        let timeout = Duration::from_millis(50);
+        let result = generate("broken", shared(Duration::from_secs(1)), timeout, |_| {});
+        assert_eq!(result, Err(SourceError::Timeout(timeout)));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut manager = SourceManager::new(false);
+        manager.load(vec![SourceEntry {
+            source: Box::new(Echo),
+            prefix: None,
+        }]);
+
+        // Only the text the prompt settles on is asked about
+        manager.set_query("fo");
+        manager.poll();
+        manager.set_query("foo");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let titles = loop {
+            manager.poll();
+            let titles: Vec<String> = manager
+                .items
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|item| item.from_query)
+                .map(|item| item.title.clone())
+                .collect();
+            if !titles.is_empty() || Instant::now() > deadline {
+                break titles;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(titles, vec!["foo".to_string()]);
+    }
+
+    #[test]
+    fn test_refreshed_items_differ() {
+        let mut manager = SourceManager::new(false);
+        manager.load(vec![SourceEntry {
+            source: Box::new(Same),
+            prefix: None,
+        }]);
+        wait_runs(&mut manager);
+        let first = manager.items.lock().unwrap().clone();
+
+        manager.refresh("same");
+        wait_runs(&mut manager);
+        let second = manager.items.lock().unwrap().clone();
+
+        // Lists holding the first run's item must take the new one and its actions
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert_eq!(first[0].title, second[0].title);
+        assert!(first[0] != second[0]);
+    }
+
+    #[test]
+    fn test_no_runs_while_hung() {
+        let mut manager = SourceManager::new(false);
+        manager.load(vec![SourceEntry {
+            source: Box::new(Hang),
+            prefix: None,
+        }]);
+
+        wait_runs(&mut manager);
+        assert!(matches!(
+            manager.statuses.lock().unwrap()[0].1,
+            SourceStatus::Failed(SourceError::Timeout(_))
+        ));
+
+        // The timed out run still has the source, refreshes wait for it instead of piling up
+        manager.refresh("hang");
+        manager.refresh("hang");
+        assert!(manager.runs.is_empty());
+        assert!(manager.sources[0].pending);
+    }
+}
@@ -1,30 +1,285 @@
+use std::{fmt::Display, sync::mpsc::Sender, time::Duration};
+
 use self::actions::Action;
 
 pub mod actions;
 pub mod apps;
+pub mod cache;
 pub mod dummy;
 pub mod lua;
+pub mod lua_api;
+pub mod manager;
+pub mod plugins;
 pub mod secrets;
+pub mod stdin;
 pub mod tmux;
+pub mod watcher;
 pub mod windows;
 
 #[derive(Clone)]
 pub struct SourceItem {
+    // Stable identity, used for dedup and history
+    pub id: String,
+    // Name of the source that generated this item
+    pub source: String,
     pub icon: Option<String>,
     pub title: String,
-    pub action: Box<dyn Action + Send>,
+    pub subtitle: Option<String>,
+    // Extra terms the item can be found by, not displayed
+    pub keywords: Vec<String>,
+    // Added to the match score when ranking
+    pub score_boost: i32,
+    // Configured for the source, replaces the action tag
+    pub prefix: Option<String>,
+    // Answer of a dynamic source to the typed text, listed even if it doesn't match it
+    pub from_query: bool,
+    // Run or query of the manager that produced it, an item emitted again by a later run
+    // carries new actions even if it looks the same
+    pub run: u64,
+    // Primary action first, the rest are offered in the actions menu
+    pub actions: Vec<Box<dyn Action + Send>>,
+}
+
+impl SourceItem {
+    pub fn new(
+        source: impl AsRef<str>,
+        title: impl AsRef<str>,
+        action: Box<dyn Action + Send>,
+    ) -> SourceItem {
+        let id = format!(
+            "{}:{}:{}",
+            source.as_ref(),
+            action.tags().first().unwrap(),
+            title.as_ref()
+        );
+
+        SourceItem {
+            id,
+            source: source.as_ref().to_string(),
+            icon: None,
+            title: title.as_ref().to_string(),
+            subtitle: None,
+            keywords: Vec::new(),
+            score_boost: 0,
+            prefix: None,
+            from_query: false,
+            run: 0,
+            actions: vec![action],
+        }
+    }
+
+    // Override the default source + tag + title identity, for sources that have a better one
+    pub fn with_id(mut self, id: impl AsRef<str>) -> Self {
+        self.id = format!("{}:{}", self.source, id.as_ref());
+        self
+    }
+    pub fn with_icon(mut self, icon: Option<String>) -> Self {
+        self.icon = icon;
+        self
+    }
+    pub fn with_subtitle(mut self, subtitle: Option<String>) -> Self {
+        self.subtitle = subtitle.filter(|s| !s.is_empty());
+        self
+    }
+    pub fn with_keywords(mut self, keywords: Vec<String>) -> Self {
+        self.keywords = keywords;
+        self
+    }
+    pub fn with_score_boost(mut self, score_boost: i32) -> Self {
+        self.score_boost = score_boost;
+        self
+    }
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+    pub fn with_action(mut self, action: Box<dyn Action + Send>) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    pub fn action(&self) -> &(dyn Action + Send) {
+        self.actions.first().unwrap().as_ref()
+    }
+
+    pub fn tag(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => prefix.clone(),
+            None => self.action().tags().first().unwrap().clone(),
+        }
+    }
+
+    // One item per action, shown in the actions menu. They keep the parent id so history
+    // credits the parent item whichever action is used
+    pub fn action_items(&self) -> Vec<SourceItem> {
+        self.actions
+            .iter()
+            .map(|action| SourceItem {
+                id: self.id.clone(),
+                source: self.source.clone(),
+                icon: self.icon.clone(),
+                title: action.name(),
+                subtitle: Some(self.title.clone()),
+                keywords: Vec::new(),
+                score_boost: 0,
+                prefix: self.prefix.clone(),
+                from_query: self.from_query,
+                run: self.run,
+                actions: vec![action.clone()],
+            })
+            .collect()
+    }
 }
 
 impl PartialEq for SourceItem {
     fn eq(&self, other: &Self) -> bool {
-        self.icon == other.icon
+        self.id == other.id
+            && self.icon == other.icon
             && self.title == other.title
-            && self.action.tags() == other.action.tags()
+            && self.subtitle == other.subtitle
+            && self.run == other.run
+    }
+}
+
+// Sources taking longer than this are reported as failed, whatever they return later is dropped
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceError {
+    // Something the source depends on isn't there, e.g. no X server or tmux not installed
+    Unavailable(String),
+    Failed(String),
+    Timeout(Duration),
+    Panicked(String),
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::Unavailable(reason) => write!(f, "unavailable: {}", reason),
+            SourceError::Failed(reason) => write!(f, "failed: {}", reason),
+            SourceError::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            SourceError::Panicked(reason) => write!(f, "panicked: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+impl From<std::io::Error> for SourceError {
+    fn from(err: std::io::Error) -> Self {
+        SourceError::Failed(err.to_string())
+    }
+}
+
+impl From<mlua::Error> for SourceError {
+    fn from(err: mlua::Error) -> Self {
+        SourceError::Failed(err.to_string())
     }
 }
 
+impl From<xcb::Error> for SourceError {
+    fn from(err: xcb::Error) -> Self {
+        SourceError::Failed(err.to_string())
+    }
+}
+
+// What sources report while generating, every run ends with a single Done
+#[derive(Clone)]
+pub enum SourceEvent {
+    Batch {
+        source: String,
+        items: Vec<SourceItem>,
+    },
+    // Number of items sent, or why the run failed
+    Done {
+        source: String,
+        result: Result<usize, SourceError>,
+    },
+}
+
+// Handed to generate_items, items sent through it show up in the list right away
+#[derive(Clone)]
+pub struct ItemSink {
+    source: String,
+    tx: Sender<SourceEvent>,
+}
+
+impl ItemSink {
+    pub fn new(source: impl AsRef<str>, tx: Sender<SourceEvent>) -> ItemSink {
+        ItemSink {
+            source: source.as_ref().to_string(),
+            tx,
+        }
+    }
+
+    // Returns false once nobody is listening anymore, e.g. after a timeout, the source can
+    // stop there
+    pub fn send(&self, items: Vec<SourceItem>) -> bool {
+        if items.is_empty() {
+            return true;
+        }
+        self.tx
+            .send(SourceEvent::Batch {
+                source: self.source.clone(),
+                items,
+            })
+            .is_ok()
+    }
+}
+
+// When a source is queried again after the first load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefreshPolicy {
+    // Only on reload, or when its watched directories change
+    Static,
+    // Every time the window is shown, for things that change all the time like windows
+    OnShow,
+    // Periodically, in the background
+    Interval(Duration),
+}
+
+// A source as set up in config.lua
+pub struct SourceEntry {
+    pub source: Box<dyn Source + Send>,
+    pub prefix: Option<String>,
+}
+
 pub trait Source {
-    fn generate_items(&self) -> Vec<SourceItem>;
+    // Short identifier, e.g. "apps". Items produced by the source carry it
+    fn name(&self) -> String;
+
+    // Send items through `sink` as they are produced, in as many batches as it takes
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError>;
+
+    // Directories the items are generated from. Sources that have any are kept in the on-disk
+    // index and only rescanned when one of them changes
+    fn watched_dirs(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Called with every changed path under `watched_dirs`, the source is regenerated if true
+    fn needs_refresh(&self, _path: &str) -> bool {
+        true
+    }
+
+    fn refresh_policy(&self) -> RefreshPolicy {
+        RefreshPolicy::Static
+    }
+
+    fn timeout(&self) -> Duration {
+        DEFAULT_TIMEOUT
+    }
+
+    // Sources that answer the typed text with items of their own, through `query`
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+
+    // Called off the UI thread once the prompt settles, the items replace the previous answer
+    fn query(&self, _text: &str, _sink: &ItemSink) -> Result<(), SourceError> {
+        Ok(())
+    }
 
     fn is_async(&self) -> bool; //TODO(quadrado): Use this in order async load or not, unused for
                                 //now
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+use log::warn;
+use mlua::{Lua, LuaSerdeExt};
+use serde::Deserialize;
+
+use super::{lua::LuaSource, RefreshPolicy, SourceEntry};
+
+// Plugin folders describe themselves in this file, single scripts take the defaults
+pub const MANIFEST: &str = "plugin.lua";
+const DEFAULT_MAIN: &str = "init.lua";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Refresh {
+    // "static" or "on-show"
+    Named(String),
+    // Every that many seconds
+    Seconds(u64),
+}
+
+impl Refresh {
+    fn policy(&self) -> Result<RefreshPolicy, String> {
+        match self {
+            Refresh::Named(name) if name == "static" => Ok(RefreshPolicy::Static),
+            Refresh::Named(name) if name == "on-show" => Ok(RefreshPolicy::OnShow),
+            Refresh::Named(name) => Err(format!("unknown refresh policy '{}'", name)),
+            // Would refresh on every frame
+            Refresh::Seconds(0) => Err("refresh interval must be at least 1 second".to_string()),
+            Refresh::Seconds(secs) => Ok(RefreshPolicy::Interval(Duration::from_secs(*secs))),
+        }
+    }
+}
+
+// What plugin.lua returns, e.g. return { name = "github", prefix = "gh", refresh = 600 }
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub prefix: Option<String>,
+    pub refresh: Option<Refresh>,
+    // Script to run, relative to the plugin folder
+    pub main: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Plugin {
+    pub name: String,
+    pub script: String,
+    pub prefix: Option<String>,
+    pub refresh: RefreshPolicy,
+}
+
+impl Plugin {
+    pub fn into_entry(self, password_store: Option<String>) -> SourceEntry {
+        SourceEntry {
+            source: Box::new(
+                LuaSource::new(self.script)
+                    .with_name(&self.name)
+                    .with_refresh_policy(self.refresh)
+                    .with_log(log_path(&self.name))
+                    .with_password_store(password_store),
+            ),
+            prefix: self.prefix,
+        }
+    }
+}
+
+// Where a plugin's print output and errors go, e.g. ~/.local/state/tudo/plugins/github.log
+pub fn log_path(name: &str) -> String {
+    let state_home = std::env::var("XDG_STATE_HOME").unwrap_or_else(|_| {
+        let home = std::env::var("HOME").unwrap_or_default();
+        format!("{}/.local/state", home)
+    });
+
+    format!("{}/tudo/plugins/{}.log", state_home, name)
+}
+
+pub fn append_log(path: &str, text: &str) {
+    if let Some(parent) = Path::new(path).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let res = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", text));
+    if let Err(err) = res {
+        warn!("Can't write plugin log {}: {}", path, err);
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, String> {
+    let lua = Lua::new();
+    let contents = fs::read(path).map_err(|err| err.to_string())?;
+    let value: mlua::Value = lua
+        .load(&contents)
+        .set_name(path.to_string_lossy())
+        .eval()
+        .map_err(|err| err.to_string())?;
+    lua.from_value(value).map_err(|err| err.to_string())
+}
+
+// A single script, or a folder with a manifest or an init.lua
+fn load_plugin(path: &Path) -> Option<Result<Plugin, (String, String)>> {
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+
+    let (manifest, script) = if path.is_dir() {
+        let manifest_path = path.join(MANIFEST);
+        let manifest = match manifest_path.exists() {
+            true => match read_manifest(&manifest_path) {
+                Ok(manifest) => manifest,
+                Err(err) => return Some(Err((stem, err))),
+            },
+            false if path.join(DEFAULT_MAIN).exists() => Manifest::default(),
+            // Not a plugin, e.g. a folder of shared modules
+            false => return None,
+        };
+        let main = manifest.main.as_deref().unwrap_or(DEFAULT_MAIN);
+        let script = path.join(main).to_string_lossy().to_string();
+        (manifest, script)
+    } else if path.extension().is_some_and(|ext| ext == "lua") {
+        (Manifest::default(), path.to_string_lossy().to_string())
+    } else {
+        return None;
+    };
+
+    if manifest.enabled == Some(false) {
+        return None;
+    }
+
+    let name = manifest.name.unwrap_or(stem);
+    let refresh = match manifest.refresh.map(|refresh| refresh.policy()) {
+        Some(Err(err)) => return Some(Err((name, err))),
+        Some(Ok(policy)) => policy,
+        None => RefreshPolicy::Static,
+    };
+
+    Some(Ok(Plugin {
+        name,
+        script,
+        prefix: manifest.prefix,
+        refresh,
+    }))
+}
+
+// Plugins in `dir`, in name order. Broken ones are left out and the reason goes to their log
+pub fn discover(dir: impl AsRef<Path>) -> Vec<Plugin> {
+    let Ok(entries) = fs::read_dir(dir.as_ref()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    let mut names = HashSet::new();
+    let mut plugins = Vec::new();
+    for path in paths {
+        match load_plugin(&path) {
+            Some(Ok(plugin)) if !names.insert(plugin.name.clone()) => {
+                warn!(
+                    "Skipping plugin {}, another plugin is named '{}'",
+                    path.display(),
+                    plugin.name
+                );
+            }
+            Some(Ok(plugin)) => plugins.push(plugin),
+            Some(Err((name, err))) => {
+                warn!("Skipping plugin {}: {}", path.display(), err);
+                append_log(&log_path(&name), &format!("error: {}", err));
+            }
+            None => (),
+        }
+    }
+    plugins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover() {
+        let dir = std::env::temp_dir().join(format!("tudo-plugins-{}", std::process::id()));
+        fs::create_dir_all(dir.join("github")).unwrap();
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("notes.lua"), "return {}").unwrap();
+        fs::write(dir.join("README.md"), "").unwrap();
+        fs::write(
+            dir.join("github").join(MANIFEST),
+            "return { name = 'gh', prefix = 'g', refresh = 600, main = 'main.lua' }",
+        )
+        .unwrap();
+
+        let plugins = discover(&dir);
+        assert_eq!(
+            plugins,
+            vec![
+                Plugin {
+                    name: "gh".to_string(),
+                    script: dir.join("github/main.lua").to_string_lossy().to_string(),
+                    prefix: Some("g".to_string()),
+                    refresh: RefreshPolicy::Interval(Duration::from_secs(600)),
+                },
+                Plugin {
+                    name: "notes".to_string(),
+                    script: dir.join("notes.lua").to_string_lossy().to_string(),
+                    prefix: None,
+                    refresh: RefreshPolicy::Static,
+                },
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_refresh_policy() {
+        assert_eq!(
+            Refresh::Seconds(60).policy(),
+            Ok(RefreshPolicy::Interval(Duration::from_secs(60)))
+        );
+        assert_eq!(
+            Refresh::Named("on-show".to_string()).policy(),
+            Ok(RefreshPolicy::OnShow)
+        );
+        assert!(Refresh::Seconds(0).policy().is_err());
+    }
+}
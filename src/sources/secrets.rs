@@ -1,8 +1,17 @@
 use std::env;
 
-use super::{actions::PassSecretAction, Source, SourceItem};
+use super::{
+    actions::{PassOtpAction, PassSecretAction, PassTypeAction, PassUsernameAction},
+    ItemSink, Source, SourceError, SourceItem,
+};
 
-pub struct Secrets {}
+// Secrets are sent in batches of this size, so big stores show up progressively
+const BATCH_SIZE: usize = 100;
+
+pub struct Secrets {
+    // Store directory from config.lua, otherwise the one pass would use
+    pub store: Option<String>,
+}
 
 impl Default for Secrets {
     fn default() -> Self {
@@ -12,25 +21,45 @@ impl Default for Secrets {
 
 impl Secrets {
     pub fn new() -> Secrets {
-        Secrets {}
+        Secrets { store: None }
+    }
+    pub fn with_store(mut self, store: impl AsRef<str>) -> Self {
+        self.store = Some(store.as_ref().to_string());
+        self
+    }
+
+    // Same lookup as pass itself
+    fn password_store_dir(&self) -> String {
+        self.store.clone().unwrap_or_else(|| {
+            env::var("PASSWORD_STORE_DIR")
+                .unwrap_or_else(|_| format!("{}/.password-store", env::var("HOME").unwrap()))
+        })
     }
 }
 
 impl Source for Secrets {
+    fn name(&self) -> String {
+        "secrets".to_string()
+    }
+    fn watched_dirs(&self) -> Vec<String> {
+        vec![self.password_store_dir()]
+    }
+    // Commits to the store's git repository don't change the list of secrets
+    fn needs_refresh(&self, path: &str) -> bool {
+        !path.contains("/.git")
+    }
     fn is_async(&self) -> bool {
         false
     }
-    fn generate_items(&self) -> Vec<SourceItem> {
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
         let mut res: Vec<SourceItem> = Vec::new();
 
-        let home_path = format!("{}/.password-store", env::var("HOME").unwrap());
-
-        match std::fs::read_dir(home_path) {
+        match std::fs::read_dir(self.password_store_dir()) {
             Ok(dir) => {
                 for file in dir {
                     // Secret name will be the file name minus the extension
                     // Use it as the item title
-                    let filename = file.unwrap().file_name().into_string().unwrap();
+                    let filename = file?.file_name().to_string_lossy().to_string();
 
                     // Ignore hidden files and .gpg-id
                     if filename.starts_with(".") {
@@ -39,17 +68,44 @@ impl Source for Secrets {
 
                     let secret_name = filename.split(".gpg").next().unwrap();
 
-                    res.push(SourceItem {
-                        title: secret_name.to_string(),
-                        action: Box::new(PassSecretAction {
+                    res.push(
+                        SourceItem::new(
+                            self.name(),
+                            secret_name,
+                            Box::new(PassSecretAction {
+                                secret_name: secret_name.to_string(),
+                                store: self.store.clone(),
+                            }),
+                        )
+                        .with_id(secret_name)
+                        .with_action(Box::new(PassOtpAction {
+                            secret_name: secret_name.to_string(),
+                            store: self.store.clone(),
+                        }))
+                        .with_action(Box::new(PassUsernameAction {
+                            secret_name: secret_name.to_string(),
+                            store: self.store.clone(),
+                        }))
+                        .with_action(Box::new(PassTypeAction {
                             secret_name: secret_name.to_string(),
-                        }),
-                        icon: None,
-                    });
+                            store: self.store.clone(),
+                        })),
+                    );
+
+                    if res.len() >= BATCH_SIZE && !sink.send(std::mem::take(&mut res)) {
+                        return Ok(());
+                    }
                 }
             }
-            Err(_) => println!("No password store folder was found."),
+            Err(err) => {
+                return Err(SourceError::Unavailable(format!(
+                    "no password store at {}: {}",
+                    self.password_store_dir(),
+                    err
+                )))
+            }
         };
-        res
+        sink.send(res);
+        Ok(())
     }
 }
@@ -0,0 +1,53 @@
+use std::{io::BufRead, time::Duration};
+
+use super::{actions::PrintAction, ItemSink, Source, SourceError, SourceItem};
+
+// Items read from stdin, one per line. Used by the dmenu mode.
+pub struct Stdin {}
+
+impl Default for Stdin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stdin {
+    pub fn new() -> Stdin {
+        Stdin {}
+    }
+}
+
+impl Source for Stdin {
+    fn name(&self) -> String {
+        "stdin".to_string()
+    }
+    fn is_async(&self) -> bool {
+        false
+    }
+    // Whatever writes to our stdin may take its time
+    fn timeout(&self) -> Duration {
+        Duration::MAX
+    }
+    // Lines are sent as soon as they are read, so slow producers fill the list progressively
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
+        for (idx, line) in std::io::stdin().lock().lines().enumerate() {
+            let line = line?;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            // Lines may repeat, identify them by position
+            let item = SourceItem::new(
+                self.name(),
+                &line,
+                Box::new(PrintAction { text: line.clone() }),
+            )
+            .with_id(idx.to_string());
+            if !sink.send(vec![item]) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
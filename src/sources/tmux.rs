@@ -2,9 +2,12 @@ use std::process::Command;
 
 use crate::sources::actions::TmuxAction;
 
-use super::{Source, SourceItem};
+use super::{ItemSink, RefreshPolicy, Source, SourceError, SourceItem};
 
-pub struct Tmux {}
+pub struct Tmux {
+    // Sessions are attached in it, it has to take the command to run as the last arguments
+    terminal: String,
+}
 
 impl Default for Tmux {
     fn default() -> Self {
@@ -14,30 +17,52 @@ impl Default for Tmux {
 
 impl Tmux {
     pub fn new() -> Tmux {
-        Tmux {}
+        Tmux {
+            terminal: "alacritty -e".to_string(),
+        }
+    }
+
+    pub fn with_terminal(mut self, terminal: impl AsRef<str>) -> Self {
+        self.terminal = terminal.as_ref().to_string();
+        self
     }
 }
 
 impl Source for Tmux {
+    fn name(&self) -> String {
+        "tmux".to_string()
+    }
     fn is_async(&self) -> bool {
         false
     }
-    fn generate_items(&self) -> Vec<SourceItem> {
+    fn refresh_policy(&self) -> RefreshPolicy {
+        RefreshPolicy::OnShow
+    }
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
         let mut res: Vec<SourceItem> = Vec::new();
 
-        let output = Command::new("sh").args(["-c", "tmux ls"]).output();
-        let ot = String::from_utf8(output.unwrap().stdout).unwrap();
+        // A failing `tmux ls` just means there's no server running, so no sessions
+        let output = Command::new("tmux")
+            .arg("ls")
+            .output()
+            .map_err(|err| SourceError::Unavailable(format!("can't run tmux: {}", err)))?;
+        let ot = String::from_utf8_lossy(&output.stdout);
 
         for line in ot.lines() {
             let session_name = line.split(":").next().unwrap();
-            res.push(SourceItem {
-                title: line.to_string(),
-                icon: None,
-                action: Box::new(TmuxAction {
-                    session: session_name.to_string(),
-                }),
-            });
+            res.push(
+                SourceItem::new(
+                    self.name(),
+                    line,
+                    Box::new(TmuxAction {
+                        session: session_name.to_string(),
+                        terminal: self.terminal.clone(),
+                    }),
+                )
+                .with_id(session_name),
+            );
         }
-        res
+        sink.send(res);
+        Ok(())
     }
 }
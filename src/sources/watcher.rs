@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use log::{debug, warn};
+
+// Changes arriving this close together are reported once, package managers touch a lot of
+// files in one go
+const DEBOUNCE: Duration = Duration::from_millis(500);
+// How long the watching thread waits for events before checking if the watcher was dropped
+const POLL_TIMEOUT_MS: i32 = 200;
+
+// A change on disk, the name of the source watching it and the changed path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub source: String,
+    pub path: String,
+}
+
+// Watches the directories of every source, and their subdirectories, with inotify
+pub struct Watcher {
+    rx: Receiver<Change>,
+    // Tells the thread to stop, which closes the inotify instance along with all its watches
+    stop: Arc<AtomicBool>,
+}
+
+fn watch_mask() -> WatchMask {
+    WatchMask::CREATE
+        | WatchMask::DELETE
+        | WatchMask::MOVED_TO
+        | WatchMask::MOVED_FROM
+        | WatchMask::CLOSE_WRITE
+}
+
+fn add_recursive(
+    watches: &mut Watches,
+    dir: &Path,
+    source: &str,
+    descriptors: &mut HashMap<WatchDescriptor, (String, String)>,
+) {
+    // Git repositories, e.g. the password store's, change on every commit and hold lots of
+    // directories
+    if dir.file_name().is_some_and(|name| name == ".git") {
+        return;
+    }
+
+    match watches.add(dir, watch_mask()) {
+        Ok(wd) => {
+            descriptors.insert(wd, (source.to_string(), dir.to_string_lossy().to_string()));
+        }
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                warn!("Can't watch {}: {}", dir.display(), err);
+            }
+            return;
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                add_recursive(watches, &entry.path(), source, descriptors);
+            }
+        }
+    }
+}
+
+impl Watcher {
+    // `dirs` is a list of (source name, directories) pairs
+    pub fn new(dirs: Vec<(String, Vec<String>)>) -> std::io::Result<Watcher> {
+        let mut inotify = Inotify::init()?;
+        let mut watches = inotify.watches();
+        let mut descriptors: HashMap<WatchDescriptor, (String, String)> = HashMap::new();
+
+        for (source, source_dirs) in dirs.iter() {
+            for dir in source_dirs {
+                add_recursive(&mut watches, Path::new(dir), source, &mut descriptors);
+            }
+        }
+        debug!("Watching {} directories", descriptors.len());
+
+        let (tx, rx) = channel::<Change>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+
+            loop {
+                if thread_stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                if !wait_readable(&inotify) {
+                    continue;
+                }
+
+                let mut changes: Vec<Change> = Vec::new();
+
+                match inotify.read_events(&mut buffer) {
+                    Ok(events) => {
+                        for event in events {
+                            collect(event, &mut watches, &mut descriptors, &mut changes);
+                        }
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                    Err(err) => {
+                        warn!("Stopped watching for changes: {}", err);
+                        return;
+                    }
+                }
+
+                // Wait for the burst to settle and take everything that came with it
+                thread::sleep(DEBOUNCE);
+                while let Ok(events) = inotify.read_events(&mut buffer) {
+                    let mut any = false;
+                    for event in events {
+                        any = true;
+                        collect(event, &mut watches, &mut descriptors, &mut changes);
+                    }
+                    if !any {
+                        break;
+                    }
+                }
+
+                if !send(&tx, changes) {
+                    return;
+                }
+            }
+        });
+
+        Ok(Watcher { rx, stop })
+    }
+
+    // Changes since the last call, without blocking
+    pub fn changes(&self) -> Vec<Change> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+// Whether there are events to read, gives up after POLL_TIMEOUT_MS
+fn wait_readable(inotify: &Inotify) -> bool {
+    let mut fd = sdl2::libc::pollfd {
+        fd: inotify.as_raw_fd(),
+        events: sdl2::libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { sdl2::libc::poll(&mut fd, 1, POLL_TIMEOUT_MS) > 0 }
+}
+
+fn collect(
+    event: inotify::Event<&std::ffi::OsStr>,
+    watches: &mut Watches,
+    descriptors: &mut HashMap<WatchDescriptor, (String, String)>,
+    changes: &mut Vec<Change>,
+) {
+    let Some((source, dir)) = descriptors.get(&event.wd).cloned() else {
+        return;
+    };
+    let path = match event.name {
+        Some(name) => format!("{}/{}", dir, name.to_string_lossy()),
+        None => dir,
+    };
+
+    // New subdirectories need their own watch
+    if event.mask.contains(EventMask::ISDIR)
+        && event
+            .mask
+            .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+    {
+        add_recursive(watches, Path::new(&path), &source, descriptors);
+    }
+
+    changes.push(Change { source, path });
+}
+
+// Returns false once nobody is listening anymore
+fn send(tx: &Sender<Change>, changes: Vec<Change>) -> bool {
+    changes.into_iter().all(|change| tx.send(change).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_new_files() {
+        let dir = std::env::temp_dir().join(format!("tudo-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let watcher = Watcher::new(vec![(
+            "apps".to_string(),
+            vec![dir.to_string_lossy().to_string()],
+        )])
+        .unwrap();
+
+        // Files in new subdirectories are seen as well
+        fs::create_dir(dir.join("sub")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fs::write(dir.join("sub/foo.desktop"), "").unwrap();
+        thread::sleep(DEBOUNCE * 3);
+
+        let changes = watcher.changes();
+        assert!(changes.iter().all(|change| change.source == "apps"));
+        assert!(changes
+            .iter()
+            .any(|change| change.path.ends_with("sub/foo.desktop")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_skip_git() {
+        let dir = std::env::temp_dir().join(format!("tudo-watch-git-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".git/objects")).unwrap();
+
+        let watcher = Watcher::new(vec![(
+            "secrets".to_string(),
+            vec![dir.to_string_lossy().to_string()],
+        )])
+        .unwrap();
+
+        fs::write(dir.join(".git/objects/foo"), "").unwrap();
+        fs::write(dir.join("foo.gpg"), "").unwrap();
+        thread::sleep(DEBOUNCE * 3);
+
+        let changes = watcher.changes();
+        assert!(changes
+            .iter()
+            .any(|change| change.path.ends_with("foo.gpg")));
+        assert!(!changes.iter().any(|change| change.path.contains("objects")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
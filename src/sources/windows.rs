@@ -1,8 +1,8 @@
-use crate::sources::Source;
+use crate::sources::{ItemSink, RefreshPolicy, Source, SourceError};
 use xcb::x::{self, Atom, ConfigWindow, SendEventDest, Window};
-use xcb::Connection;
+use xcb::{Connection, Xid};
 
-use super::actions::WindowSwitchAction;
+use super::actions::{WindowCloseAction, WindowMoveAction, WindowSwitchAction};
 use super::SourceItem;
 
 pub struct WindowSource {}
@@ -116,6 +116,63 @@ pub fn switch_to_window(
     Ok(())
 }
 
+// Ask the window manager to close the window, same as clicking its close button
+pub fn close_window(conn: &Connection, window: &Window, root: &Window) -> Result<(), xcb::Error> {
+    let net_close_window_atom = get_atom(conn, "_NET_CLOSE_WINDOW");
+
+    conn.send_and_check_request(&x::SendEvent {
+        destination: SendEventDest::Window(*root),
+        event: &x::ClientMessageEvent::new(
+            *window,
+            net_close_window_atom,
+            // Timestamp and source indication (2 = pager)
+            x::ClientMessageData::Data32([0, 2, 0, 0, 0]),
+        ),
+        propagate: false,
+        event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+    })?;
+    Ok(())
+}
+
+pub fn move_window_to_desktop(
+    conn: &Connection,
+    window: &Window,
+    root: &Window,
+    desktop: u32,
+) -> Result<(), xcb::Error> {
+    let net_wm_desktop_atom = get_atom(conn, "_NET_WM_DESKTOP");
+
+    conn.send_and_check_request(&x::SendEvent {
+        destination: SendEventDest::Window(*root),
+        event: &x::ClientMessageEvent::new(
+            *window,
+            net_wm_desktop_atom,
+            x::ClientMessageData::Data32([desktop, 2, 0, 0, 0]),
+        ),
+        propagate: false,
+        event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+    })?;
+    Ok(())
+}
+
+fn get_number_of_desktops(conn: &Connection, root: &Window) -> u32 {
+    let net_number_of_desktops_atom = get_atom(conn, "_NET_NUMBER_OF_DESKTOPS");
+
+    let cookie = conn.send_request(&x::GetProperty {
+        window: *root,
+        delete: false,
+        long_offset: 0,
+        long_length: 1,
+        property: net_number_of_desktops_atom,
+        r#type: x::ATOM_CARDINAL,
+    });
+
+    match conn.wait_for_reply(cookie) {
+        Ok(reply) => reply.value::<u32>().first().copied().unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
 impl Default for WindowSource {
     fn default() -> Self {
         Self::new()
@@ -129,17 +186,27 @@ impl WindowSource {
 }
 
 impl Source for WindowSource {
+    fn name(&self) -> String {
+        "windows".to_string()
+    }
     fn is_async(&self) -> bool {
         false
     }
+    fn refresh_policy(&self) -> RefreshPolicy {
+        RefreshPolicy::OnShow
+    }
 
-    fn generate_items(&self) -> Vec<SourceItem> {
+    fn generate_items(&self, sink: &ItemSink) -> Result<(), SourceError> {
         let mut res: Vec<SourceItem> = Vec::new();
 
         // Connect to the X server.
-        let (conn, screen_num) = xcb::Connection::connect(None).unwrap();
+        let (conn, screen_num) = xcb::Connection::connect(None)
+            .map_err(|err| SourceError::Unavailable(err.to_string()))?;
         let setup = conn.get_setup();
-        let screen = setup.roots().nth(screen_num as usize).unwrap();
+        let screen = setup
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or(SourceError::Unavailable("no screen".to_string()))?;
 
         let net_client_list_atom = get_atom(&conn, "_NET_CLIENT_LIST");
 
@@ -152,7 +219,8 @@ impl Source for WindowSource {
             r#type: x::ATOM_WINDOW,
         });
 
-        let r = conn.wait_for_reply(c).unwrap();
+        let r = conn.wait_for_reply(c)?;
+        let desktops = get_number_of_desktops(&conn, &screen.root());
 
         for w in r.value() {
             let c = conn.send_request(&xcb::x::GetProperty {
@@ -164,19 +232,34 @@ impl Source for WindowSource {
                 r#type: x::ATOM_STRING,
             });
 
-            let r = conn.wait_for_reply(c).unwrap();
+            // Windows can go away while we ask about them
+            let Ok(r) = conn.wait_for_reply(c) else {
+                continue;
+            };
             let buf: Vec<u8> = r.value().to_vec();
             let mut split = buf.split(|item| item == &0_u8);
-            let wname = String::from_utf8(split.nth(1).unwrap().to_vec()).unwrap();
-            res.push(SourceItem {
-                action: Box::new(WindowSwitchAction {
+            let wname = String::from_utf8_lossy(split.nth(1).unwrap_or_default()).to_string();
+            // Several windows can share a class, tell them apart by X resource id
+            let mut item = SourceItem::new(
+                self.name(),
+                wname,
+                Box::new(WindowSwitchAction {
                     window: *w,
                     exit_after: true,
                 }),
-                icon: None,
-                title: wname.to_string(),
-            });
+            )
+            .with_id(w.resource_id().to_string())
+            .with_action(Box::new(WindowCloseAction { window: *w }));
+
+            for desktop in 0..desktops {
+                item = item.with_action(Box::new(WindowMoveAction {
+                    window: *w,
+                    desktop,
+                }));
+            }
+            res.push(item);
         }
-        res
+        sink.send(res);
+        Ok(())
     }
 }
@@ -1,9 +1,10 @@
 use std::any::Any;
 
-use sdl2::{pixels::Color, rect::Rect};
+use sdl2::rect::Rect;
 
 use crate::{
     app::App,
+    ui::theme::UI_FONT,
     utils::draw::{draw_rounded_rect, draw_string_texture},
 };
 
@@ -56,18 +57,19 @@ impl UIComponent for Button {
         &mut self,
         tc: &sdl2::render::TextureCreator<sdl2::video::WindowContext>,
         cache: &mut crate::utils::cache::TextureCache,
-        _app: &crate::app::App,
+        app: &crate::app::App,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         rect: sdl2::rect::Rect,
         _elapsed: u128,
     ) {
         let r = Rect::new(0, 0, rect.width() - 1, rect.height() - 1);
-        let font = cache.fonts.get_font("normal-20");
+        let theme = &app.config.theme;
+        let font = cache.fonts.get_font(UI_FONT);
         let color = match (self.state.active, self.pressed, self.get_focus()) {
-            (true, false, false) => Color::WHITE,
-            (true, true, _) => Color::RED,
-            (true, false, true) => Color::BLUE,
-            (false, _, _) => Color::GRAY,
+            (true, false, false) => theme.foreground,
+            (true, true, _) => theme.highlight,
+            (true, false, true) => theme.accent,
+            (false, _, _) => theme.muted,
         };
         let tex = draw_string_texture(self.text.clone(), tc, font, color);
         let (tw, th) = (tex.query().width, tex.query().height);
@@ -75,7 +77,7 @@ impl UIComponent for Button {
         // let text_y = (rect.h - th as i32) / 2;
         let text_y = -3;
 
-        draw_rounded_rect(canvas, r, 3, Color::RGBA(0x30, 0x30, 0x50, 255));
+        draw_rounded_rect(canvas, r, theme.border_radius, theme.selection);
         canvas
             .copy(&tex, None, Rect::new(text_x, text_y, tw, th))
             .unwrap();
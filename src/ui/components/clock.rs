@@ -3,7 +3,7 @@ use std::any::Any;
 use chrono::prelude::*;
 use sdl2::{event::Event, rect::Rect};
 
-use crate::app::App;
+use crate::{app::App, ui::theme::UI_FONT};
 
 use super::{label::Label, traits::UIComponent};
 
@@ -15,7 +15,7 @@ pub struct Clock {
 impl Clock {
     pub fn new(id: String) -> Clock {
         let mut label = Label::new(String::from("clock-label"), String::from(""));
-        label.font_name = Some(String::from("normal-20"));
+        label.font_name = Some(UI_FONT.to_string());
 
         Clock { id, label }
     }
@@ -1,8 +1,8 @@
 use std::any::Any;
 
-use sdl2::{pixels::Color, rect::Rect};
+use sdl2::rect::Rect;
 
-use crate::{app::App, utils::draw::draw_string_texture};
+use crate::{app::App, ui::theme::PROMPT_FONT, utils::draw::draw_string_texture};
 
 use super::traits::UIComponent;
 
@@ -36,15 +36,15 @@ impl UIComponent for Label {
         &mut self,
         texture_creator: &sdl2::render::TextureCreator<sdl2::video::WindowContext>,
         cache: &mut crate::utils::cache::TextureCache,
-        _app: &crate::app::App,
+        app: &crate::app::App,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         _rect: Rect,
         _elapsed: u128,
     ) {
         let font = cache
             .fonts
-            .get_font(self.font_name.clone().unwrap_or("normal-24".to_string()));
-        let color = Color::RGBA(100, 100, 100, 255);
+            .get_font(self.font_name.clone().unwrap_or(PROMPT_FONT.to_string()));
+        let color = app.config.theme.muted;
         let texture = draw_string_texture(self.text.clone(), texture_creator, font, color);
         let (w, h) = (texture.query().width, texture.query().height);
 
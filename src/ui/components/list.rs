@@ -1,33 +1,63 @@
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::usize;
 
-use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{BlendMode, Texture, TextureCreator};
 use sdl2::video::WindowContext;
-use sdl2::{event::Event, pixels::Color, rect::Rect, render::Canvas, ttf::Font, video::Window};
+use sdl2::{event::Event, rect::Rect, render::Canvas, video::Window};
 
 use crate::animation::{Animation, AnimationType};
+use crate::keymap::Command;
 use crate::sources::SourceItem;
+use crate::ui::theme::{Theme, ITEM_FONT, SUBTITLE_FONT, UI_FONT};
 use crate::utils::cache::TextureCache;
 use crate::utils::draw::{draw_string, draw_string_texture, DrawExtensions};
-use crate::utils::fuzzy::basic_contains;
+use crate::utils::font::FontSet;
+use crate::utils::fuzzy::{fuzzy_match, fuzzy_match_positions};
 use crate::App;
 
 use super::traits::UIComponent;
 
+const FRECENCY_BONUS_DIVISOR: u32 = 25;
+const MAX_FRECENCY_BONUS: u32 = 40;
+
+// Match the title first. Keywords and subtitle are a fallback worth half the score and don't
+// produce highlights.
+fn match_item(filter: &str, item: &SourceItem) -> Option<(i32, Vec<usize>)> {
+    if let Some(m) = fuzzy_match_positions(filter, &item.title) {
+        return Some(m);
+    }
+
+    item.keywords
+        .iter()
+        .chain(item.subtitle.iter())
+        .filter_map(|term| fuzzy_match(filter, term))
+        .max()
+        .map(|score| (score / 2, vec![]))
+        // The source already decided it fits what was typed
+        .or(item.from_query.then(|| (0, vec![])))
+}
+
+// Where a row goes and how it looks
+struct Row<'h> {
+    rect: Rect,
+    index: usize,
+    is_selected: bool,
+    // Matched char positions of the title
+    highlight: &'h [usize],
+}
+
 trait RenderItem<T> {
     fn render_row<'a>(
         &'a self,
         item: &'a T,
         texture_creator: &'a TextureCreator<WindowContext>,
         tex_cache: &TextureCache,
-        font: &Font,
+        font: &FontSet,
         canvas: &mut Canvas<Window>,
-        rect: Rect,
-        elapsed: u128,
-        is_selected: bool,
-        index: usize,
+        row: Row,
     ) -> Texture;
 }
 
@@ -62,16 +92,18 @@ impl RenderViewport {
         self.1 -= amount;
     }
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct SelectListState<T> {
     pub items: Vec<T>,
     pub prompt: String,
+    // History scores by item key
+    pub frecency: Arc<HashMap<String, u32>>,
 }
 
 pub struct SelectList<T> {
     pub id: String,
     pub items: Vec<T>,
-    pub foreground_color: Color,
+    pub theme: Theme,
     pub selected_index: usize,
     pub viewport: Viewport,
     pub render_viewport: RenderViewport,
@@ -79,6 +111,10 @@ pub struct SelectList<T> {
     pub vertical_bar_width: u32,
     pub row_height: u32,
     pub last_mouse_y: i32,
+    pub keep_order: bool,
+    // Matched char positions of each item title, parallel to `items`
+    pub highlights: Vec<Vec<usize>>,
+    last_state: Option<SelectListState<T>>,
 }
 
 impl UIComponent for SelectList<SourceItem> {
@@ -98,74 +134,75 @@ impl UIComponent for SelectList<SourceItem> {
     }
 
     fn set_state(&mut self, state: Box<dyn std::any::Any>) {
-        let new_state = state.downcast_ref::<SelectListState<SourceItem>>().unwrap();
+        let new_state = *state.downcast::<SelectListState<SourceItem>>().unwrap();
+
+        // State is pushed every frame, only filter and rank again when something changed
+        if self.last_state.as_ref() == Some(&new_state) {
+            return;
+        }
+
         let prompt = &new_state.prompt;
         let new_list = &new_state.items;
 
-        //FIXME(quadrado) this whole function is whack
-        // Refactor this ASAP
-        // Rewriting the fuzzy search in a generic way that returns clones is possibility
-        // in order to not have to manage original list indices
-        let mut final_list = Vec::new();
+        // Tag and value search, e.g. ":window foo"
+        let (tag, filter) = match prompt.strip_prefix(":") {
+            Some(tagged) => {
+                let (tag, filter) = tagged.split_once(" ").unwrap_or((tagged, ""));
+                (Some(tag), filter)
+            }
+            None => (None, prompt.as_str()),
+        };
 
-        if prompt.is_empty() {
-            final_list = new_list.clone();
-        } else {
-            let haystack: Vec<String>;
-            let mut matches;
-
-            // Tag and value search
-            if prompt.starts_with(":") {
-                let clean_prompt = prompt.replace(":", "");
-                let (tag, filter) = clean_prompt.split_once(" ").unwrap_or((&clean_prompt, ""));
-
-                // action type searching
-                haystack = new_list
-                    .iter()
-                    .map(|i| i.action.tags().first().unwrap().clone())
-                    .collect::<Vec<String>>();
-                matches = basic_contains(tag.to_string(), &haystack).unwrap_or_default();
-
-                // if filter present, further filter the list with another haystack
-                if !filter.is_empty() {
-                    let mut list2 = Vec::new();
-                    for m in matches.iter() {
-                        list2.push(new_list.get(m.original_idx).unwrap().clone());
-                    }
+        let mut ranked: Vec<(i32, Vec<usize>, &SourceItem)> = Vec::new();
+        let mut seen: HashSet<(&str, &str)> = HashSet::new();
 
-                    let haystack2 = list2
-                        .iter()
-                        .map(|i| i.title.clone())
-                        .collect::<Vec<String>>();
-                    matches = basic_contains(filter.to_string(), &haystack2).unwrap_or_default();
+        for item in new_list.iter() {
+            // Different sources, or a refreshing one, may hand out the same item.
+            // Actions menu entries share their parent id, so the title is part of the key
+            if !seen.insert((&item.id, &item.title)) {
+                continue;
+            }
 
-                    // We cant to the final list computation on the outside since we are getting values
-                    // from different lists based on
-                    for m in matches.iter() {
-                        final_list.push(list2.get(m.original_idx).unwrap().clone());
-                    }
-                } else {
-                    for m in matches.iter() {
-                        final_list.push(new_list.get(m.original_idx).unwrap().clone());
-                    }
-                }
-            } else {
-                // Simple title search
-                haystack = new_list
-                    .iter()
-                    .map(|i| i.title.clone())
-                    .collect::<Vec<String>>();
-                matches = basic_contains(prompt.to_string(), &haystack).unwrap_or_default();
-
-                for m in matches.iter() {
-                    final_list.push(new_list.get(m.original_idx).unwrap().clone());
+            // action type searching
+            if let Some(tag) = tag {
+                if !item.tag().to_lowercase().contains(&tag.to_lowercase()) {
+                    continue;
                 }
             }
+
+            if let Some((score, positions)) = match_item(filter, item) {
+                let frecency = new_state.frecency.get(&item.id).copied().unwrap_or(0);
+                let score = match filter.is_empty() {
+                    // Nothing typed, most used items first
+                    true => frecency as i32,
+                    // Otherwise history only nudges the match score
+                    false => {
+                        score
+                            + std::cmp::min(frecency / FRECENCY_BONUS_DIVISOR, MAX_FRECENCY_BONUS)
+                                as i32
+                    }
+                };
+                ranked.push((score + item.score_boost, positions, item));
+            }
+        }
+
+        // Best score first, ties sorted by title
+        if !self.keep_order {
+            ranked.sort_by(|(this_score, _, this), (other_score, _, other)| {
+                other_score
+                    .cmp(this_score)
+                    .then_with(|| this.title.cmp(&other.title))
+            });
         }
 
-        // Sort by title
-        final_list.sort_by(|this, other| this.title.cmp(&other.title));
+        let (final_list, highlights): (Vec<SourceItem>, Vec<Vec<usize>>) = ranked
+            .into_iter()
+            .map(|(_, positions, item)| (item.clone(), positions))
+            .unzip();
+
         self.set_list(final_list);
+        self.highlights = highlights;
+        self.last_state = Some(new_state);
     }
     fn render(
         &mut self,
@@ -176,18 +213,21 @@ impl UIComponent for SelectList<SourceItem> {
         rect: Rect,
         elapsed: u128,
     ) {
-        let font = cache.fonts.get_font("normal-20");
-        let font2 = cache.fonts.get_font("normal-16");
+        let font = cache.fonts.get_font(UI_FONT);
+        let font2 = cache.fonts.get_font(ITEM_FONT);
 
-        canvas.set_draw_color(Color::BLACK);
+        canvas.set_draw_color(self.theme.surface);
 
-        canvas.draw_filled_rounded_rect(Rect::new(0, 0, rect.w as u32, rect.h as u32), 7);
+        canvas.draw_filled_rounded_rect(
+            Rect::new(0, 0, rect.w as u32, rect.h as u32),
+            self.theme.border_radius,
+        );
         if self.items.is_empty() {
             draw_string(
                 "No items found".to_string(),
                 canvas,
                 font,
-                self.foreground_color,
+                self.theme.foreground,
                 10,
                 10,
             );
@@ -223,10 +263,12 @@ impl UIComponent for SelectList<SourceItem> {
                     cache,
                     font2,
                     canvas,
-                    Rect::new(0, 0, rect.w as u32, self.row_height),
-                    elapsed,
-                    idx == self.selected_index,
-                    idx,
+                    Row {
+                        rect: Rect::new(0, 0, rect.w as u32, self.row_height),
+                        index: idx,
+                        is_selected: idx == self.selected_index,
+                        highlight: self.highlights.get(idx).map_or(&[], |h| h.as_slice()),
+                    },
                 );
                 canvas
                     .with_texture_canvas(&mut all_rows, |c| {
@@ -255,33 +297,13 @@ impl UIComponent for SelectList<SourceItem> {
     }
     fn handle_event(&mut self, event: &Event, app: &mut App, _: u128) {
         match event {
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Return),
-                ..
-            }
-            | sdl2::event::Event::MouseButtonDown { .. } => {
-                if self.get_selected_item().as_ref().is_some() {
-                    (self.on_select)(self.get_selected_item().as_ref().unwrap(), app)
-                }
-            }
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::P),
-                keymod: sdl2::keyboard::Mod::LCTRLMOD,
-                ..
-            } => self.select_up(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::N),
-                keymod: sdl2::keyboard::Mod::LCTRLMOD,
-                ..
-            } => self.select_down(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Up),
-                ..
-            } => self.select_up(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Down),
-                ..
-            } => self.select_down(),
+            sdl2::event::Event::KeyDown { .. } => match app.keymap.lookup(event) {
+                Some(Command::Execute) => self.select(app),
+                Some(Command::SelectPrev) => self.select_up(),
+                Some(Command::SelectNext) => self.select_down(),
+                _ => (),
+            },
+            sdl2::event::Event::MouseButtonDown { .. } => self.select(app),
             sdl2::event::Event::MouseWheel { y, .. } => {
                 if *y == 1 {
                     self.viewport.up(self.row_height as i32);
@@ -312,7 +334,7 @@ impl<T: PartialEq> SelectList<T> {
             id: id.as_ref().to_string(),
             items: Vec::<T>::new(),
             selected_index: 0,
-            foreground_color: Color::RGBA(255, 255, 255, 255),
+            theme: Theme::default(),
             viewport: Viewport {
                 top: 0,
                 bottom: 10,
@@ -321,14 +343,30 @@ impl<T: PartialEq> SelectList<T> {
             render_viewport: RenderViewport(0, 100, 0),
             vertical_bar_width: 5,
             on_select: |_, _| (),
-            row_height: 34, // Make this the same height as the font
+            row_height: Theme::default().row_height,
             last_mouse_y: 0,
+            keep_order: false,
+            highlights: Vec::new(),
+            last_state: None,
         }
     }
     pub fn with_on_select(mut self, func: fn(&T, &mut App)) -> Self {
         self.on_select = func;
         self
     }
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.row_height = theme.row_height;
+        self.theme = theme.clone();
+        self
+    }
+
+    // Run on_select on the selected item, if any
+    pub fn select(&self, app: &mut App) {
+        if let Some(item) = self.get_selected_item() {
+            (self.on_select)(item, app)
+        }
+    }
+
     pub fn select_up(&mut self) {
         if self.selected_index > 0 {
             self.selected_index -= 1;
@@ -378,13 +416,16 @@ impl RenderItem<SourceItem> for SelectList<SourceItem> {
         item: &SourceItem,
         texture_creator: &'a TextureCreator<WindowContext>,
         cache: &TextureCache,
-        font: &Font,
+        font: &FontSet,
         canvas: &mut Canvas<Window>,
-        rect: Rect,
-        _elapsed: u128,
-        is_selected: bool,
-        index: usize,
+        row: Row,
     ) -> Texture {
+        let Row {
+            rect,
+            index,
+            is_selected,
+            highlight,
+        } = row;
         let mut tex = texture_creator
             .create_texture_target(PixelFormatEnum::RGBA8888, rect.w as u32, rect.h as u32)
             .unwrap();
@@ -397,58 +438,100 @@ impl RenderItem<SourceItem> for SelectList<SourceItem> {
             .with_texture_canvas(&mut tex, |canvas| {
                 //canvas.set_draw_color(Color::BLUE);
                 if is_selected {
-                    canvas.set_draw_color(Color::RGBA(20, 20, 50, 255));
+                    canvas.set_draw_color(self.theme.selection);
                     canvas.clear();
 
-                    canvas.set_draw_color(Color::RGBA(0, 0, 255, 255));
+                    canvas.set_draw_color(self.theme.accent);
                     canvas
                         .fill_rect(Rect::new(0, 0, self.vertical_bar_width, rect.h as u32))
                         .unwrap();
                 } else if index % 2 == 0 {
-                    canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
+                    canvas.set_draw_color(self.theme.surface);
                     canvas.clear();
                 } else {
-                    canvas.set_draw_color(Color::RGBA(10, 10, 10, 255));
+                    canvas.set_draw_color(self.theme.surface_alt);
                     canvas.clear();
                 }
 
                 // Draw icon
                 let icon_height: u32 = 32;
-                if item.icon.is_some() {
-                    let icon_texture = cache.images.get_image(item.icon.as_ref().unwrap());
+                // Rows with an icon that doesn't load go without one
+                if let Some(icon_texture) = item
+                    .icon
+                    .as_ref()
+                    .and_then(|icon| cache.images.get_image_with_size(icon, icon_height))
+                {
+                    let icon_y = (rect.height() - icon_height) as i32 / 2;
                     canvas
                         .copy(
                             icon_texture,
                             None,
-                            Rect::new(vertical_bar_spacing, 0, icon_height, icon_height),
+                            Rect::new(vertical_bar_spacing, icon_y, icon_height, icon_height),
                         )
                         .unwrap();
                 }
 
-                // Draw text
-                let text_texture = draw_string_texture(
-                    item.title.clone(),
-                    texture_creator,
-                    font,
-                    self.foreground_color,
-                );
-                let query = text_texture.query();
-                let (w, h) = (query.width, query.height);
-                let hpad = (rect.height() - h) / 2;
-                canvas
-                    .copy(
-                        &text_texture,
-                        None,
-                        Some(Rect::new(vertical_bar_spacing + 34, hpad as i32, w, h)),
-                    )
-                    .unwrap();
+                // With a subtitle the title moves up and the subtitle goes on a second line
+                let (_, title_height) = font.size_of(&item.title).unwrap_or((0, 0));
+                let title_y = match &item.subtitle {
+                    Some(_) => padding,
+                    None => (rect.height() as i32 - title_height as i32) / 2,
+                };
+
+                if let Some(subtitle) = &item.subtitle {
+                    let subtitle_texture = draw_string_texture(
+                        subtitle.clone(),
+                        texture_creator,
+                        cache.fonts.get_font(SUBTITLE_FONT),
+                        self.theme.muted,
+                    );
+                    let query = subtitle_texture.query();
+                    let (w, h) = (query.width, query.height);
+                    canvas
+                        .copy(
+                            &subtitle_texture,
+                            None,
+                            Some(Rect::new(
+                                vertical_bar_spacing + 34,
+                                title_y + title_height as i32,
+                                w,
+                                h,
+                            )),
+                        )
+                        .unwrap();
+                }
+
+                // Draw text, split in runs so the matched characters get highlighted
+                let mut runs: Vec<(String, bool)> = Vec::new();
+                for (idx, ch) in item.title.chars().enumerate() {
+                    let matched = highlight.contains(&idx);
+                    match runs.last_mut() {
+                        Some((run, run_matched)) if *run_matched == matched => run.push(ch),
+                        _ => runs.push((ch.to_string(), matched)),
+                    }
+                }
+
+                let mut x = vertical_bar_spacing + 34;
+                for (run, matched) in runs {
+                    let color = match matched {
+                        true => self.theme.highlight,
+                        false => self.theme.foreground,
+                    };
+                    let text_texture = draw_string_texture(run, texture_creator, font, color);
+                    let query = text_texture.query();
+                    let (w, h) = (query.width, query.height);
+                    canvas
+                        .copy(&text_texture, None, Some(Rect::new(x, title_y, w, h)))
+                        .unwrap();
+                    x += w as i32;
+                }
 
                 // Draw tag
                 let tag_texture = draw_string_texture(
-                    format!(":{}", item.action.tags().first().unwrap().clone()),
+                    format!(":{}", item.tag()),
                     texture_creator,
                     font,
-                    Color::RGBA(128, 128, 128, 128),
+                    self.theme.muted,
                 );
                 let query = tag_texture.query();
                 let (w, h) = (query.width, query.height);
@@ -1,7 +1,6 @@
 use std::any::Any;
-use std::process::Command;
+use std::process;
 
-use sdl2::keyboard::Keycode;
 use sdl2::render::{BlendMode, TextureCreator};
 use sdl2::video::WindowContext;
 use sdl2::{event::Event, pixels::Color, rect::Rect, render::Canvas, video::Window};
@@ -9,9 +8,10 @@ use sdl2::{event::Event, pixels::Color, rect::Rect, render::Canvas, video::Windo
 use crate::animation::{Animation, AnimationType};
 use crate::app::App;
 use crate::config::Config;
+use crate::keymap::Command;
+use crate::ui::theme::PROMPT_FONT;
 use crate::utils::cache::TextureCache;
 use crate::utils::draw::{draw_string_texture, DrawExtensions};
-use crate::utils::font::FontConfig;
 
 use super::traits::UIComponent;
 
@@ -38,7 +38,7 @@ impl TextInput {
         TextInput {
             id: id.as_ref().to_string(),
             text_changed: false,
-            foreground_color: config.prompt_color,
+            foreground_color: config.theme.foreground,
             cursor_x: 0,
             blink: config.cursor_blink,
             last_blink: None,
@@ -131,6 +131,23 @@ impl TextInput {
         }
     }
 
+    // Whitespace right before the cursor goes too, like Ctrl+W in a shell
+    pub fn delete_word_before_cursor(&mut self) {
+        let chars: Vec<char> = self.state.text.chars().collect();
+        let end = self.state.cursor_position.min(chars.len());
+        let mut start = end;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+
+        let text: String = chars[..start].iter().chain(chars[end..].iter()).collect();
+        self.set_text(text);
+        self.state.cursor_position = start;
+    }
+
     pub fn cursor_left(&mut self) {
         if self.state.cursor_position > 0 {
             self.state.cursor_position -= 1;
@@ -183,15 +200,14 @@ impl UIComponent for TextInput {
     ) {
         // Draw outline and set transparency
         canvas.set_blend_mode(BlendMode::Blend);
-        canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
-        canvas.draw_filled_rounded_rect(Rect::new(1, 1, rect.width() - 2, rect.height() - 2), 7);
+        let theme = &app.config.theme;
+        canvas.set_draw_color(theme.surface);
+        canvas.draw_filled_rounded_rect(
+            Rect::new(1, 1, rect.width() - 2, rect.height() - 2),
+            theme.border_radius,
+        );
 
-        cache.fonts.load_font(FontConfig {
-            alias: "normal-24".to_string(),
-            family: app.config.font_family.clone(),
-            point_size: 24,
-        });
-        let font = cache.fonts.get_font("normal-24");
+        let font = cache.fonts.get_font(PROMPT_FONT);
         let (_fw, fh) = font.size_of(" ").unwrap();
 
         let draw_cursor = !self.state.text.is_empty() || self.input_hint.is_none();
@@ -200,7 +216,7 @@ impl UIComponent for TextInput {
                 hint.to_string(),
                 texture_creator,
                 font,
-                Color::RGBA(100, 100, 100, 255),
+                theme.muted,
             )),
             (0, None) => None,
             _ => Some(draw_string_texture(
@@ -248,55 +264,39 @@ impl UIComponent for TextInput {
                 false => 255.0,
             };
 
-            canvas.set_draw_color(Color::RGBA(0, 0, 255, alpha as u8));
+            let accent = theme.accent;
+            canvas.set_draw_color(Color::RGBA(accent.r, accent.g, accent.b, alpha as u8));
             canvas.fill_rect(cursor_rect).unwrap();
         }
     }
     fn handle_event(&mut self, event: &Event, ctx: &mut App, _: u128) {
-        match event {
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Return),
-                ..
-            } => {
-                if self.state.text.starts_with("!") {
+        if let sdl2::event::Event::TextInput { text, .. } = event {
+            // Ignore text input with ctrl pressed because of wayland reasons
+            // On key press repeat a text input event is emitted
+            if ctx.ctrl_pressed {
+                return;
+            }
+            self.insert_at_cursor(text.to_string());
+            return;
+        }
+
+        match ctx.keymap.lookup(event) {
+            Some(Command::Execute) => {
+                if self.state.text.starts_with("!") && !ctx.dmenu {
                     let t = self.state.text.replace("!", "");
                     let args = vec!["-c", &t];
-                    let _cmd = Command::new("sh").args(args).spawn();
+                    let _cmd = process::Command::new("sh").args(args).spawn();
                     ctx.should_hide = true;
                 }
             }
-            sdl2::event::Event::TextInput { text, .. } => {
-                // Ignore text input with ctrl pressed because of wayland reasons
-                // On key press repeat a text input event is emitted
-                if ctx.ctrl_pressed {
-                    return;
-                }
-                self.insert_at_cursor(text.to_string());
-            }
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Backspace),
-                ..
-            } => self.delete_before_cursor(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Left),
-                ..
-            } => self.cursor_left(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Right),
-                ..
-            } => self.cursor_right(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Home),
-                ..
-            } => self.cursor_home(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::End),
-                ..
-            } => self.cursor_end(),
-            sdl2::event::Event::KeyDown {
-                keycode: Some(Keycode::Delete),
-                ..
-            } => self.delete_at_cursor(),
+            Some(Command::DeleteBack) => self.delete_before_cursor(),
+            Some(Command::DeleteForward) => self.delete_at_cursor(),
+            Some(Command::DeleteWordBack) => self.delete_word_before_cursor(),
+            Some(Command::ClearLine) => self.clear(),
+            Some(Command::CursorLeft) => self.cursor_left(),
+            Some(Command::CursorRight) => self.cursor_right(),
+            Some(Command::CursorHome) => self.cursor_home(),
+            Some(Command::CursorEnd) => self.cursor_end(),
             _ => (),
         };
     }
@@ -10,6 +10,7 @@ use dbus::{
     message::SignalArgs,
     Message,
 };
+use log::debug;
 use sdl2::rect::Rect;
 
 use crate::{app::App, utils::xdg::IconFinder};
@@ -79,7 +80,7 @@ impl Tray {
             let mut split = item.splitn(2, "/");
             let svc = split.next().unwrap();
             let object = format!("/{}", split.next().unwrap());
-            println!("svc : {}, object: {}", svc, object);
+            debug!("svc : {}, object: {}", svc, object);
             let proxy = self
                 .conn
                 .with_proxy(svc, object, Duration::from_millis(2000));
@@ -128,13 +129,11 @@ impl UIComponent for Tray {
     ) {
         let mut x: i32 = 0;
         for p in self.items.iter().map(|x| &x.icon_path) {
-            let tex = cache.images.get_image(p);
-            let _w = tex.query().width;
-            let _h = tex.query().width;
-
-            canvas
-                .copy(tex, None, Some(Rect::new(x, 0, 24, 24)))
-                .unwrap();
+            if let Some(tex) = cache.images.get_image_with_size(p, 24) {
+                canvas
+                    .copy(tex, None, Some(Rect::new(x, 0, 24, 24)))
+                    .unwrap();
+            }
             x += 24_i32 + 5;
         }
     }
@@ -1,6 +1,6 @@
 use std::any::Any;
 
-use sdl2::{pixels::Color, rect::Rect};
+use sdl2::rect::Rect;
 
 use crate::{
     app::App,
@@ -56,7 +56,7 @@ impl UIComponent for Workspaces {
     ) {
         self.builder.generate(rect.w as usize, rect.h as usize);
         // Set draw color and clear
-        let clear_color = Color::RGBA(24, 24, 33, 255);
+        let clear_color = app.config.theme.background;
         canvas.set_draw_color(clear_color);
         canvas.clear();
 
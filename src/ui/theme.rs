@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use sdl2::pixels::Color;
+use serde::{
+    de::{Error, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// Font aliases loaded from the theme sizes, components ask the font cache for these
+pub const PROMPT_FONT: &str = "prompt";
+pub const ITEM_FONT: &str = "item";
+pub const SUBTITLE_FONT: &str = "subtitle";
+pub const UI_FONT: &str = "ui";
+
+// Colours and sizes every component draws with. Missing fields take the default theme values,
+// so config.lua can tweak a built-in theme, e.g. tudo.theme = themes.nord; tudo.theme.accent = ...
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    #[serde(with = "color")]
+    pub background: Color,
+    // Behind the prompt, the list and every other row
+    #[serde(with = "color")]
+    pub surface: Color,
+    // The rows in between
+    #[serde(with = "color")]
+    pub surface_alt: Color,
+    #[serde(with = "color")]
+    pub foreground: Color,
+    // Hints, subtitles and tags
+    #[serde(with = "color")]
+    pub muted: Color,
+    // Cursor and selection bar
+    #[serde(with = "color")]
+    pub accent: Color,
+    // Background of the selected row
+    #[serde(with = "color")]
+    pub selection: Color,
+    // Matched characters
+    #[serde(with = "color")]
+    pub highlight: Color,
+    #[serde(with = "color")]
+    pub error: Color,
+    pub border_radius: i32,
+    pub row_height: u32,
+    pub prompt_font_size: u16,
+    pub item_font_size: u16,
+    pub subtitle_font_size: u16,
+    pub ui_font_size: u16,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: Color::RGBA(24, 24, 33, 255),
+            surface: Color::RGBA(0, 0, 0, 255),
+            surface_alt: Color::RGBA(10, 10, 10, 255),
+            foreground: Color::RGBA(255, 255, 255, 255),
+            muted: Color::RGBA(128, 128, 128, 255),
+            accent: Color::RGBA(0, 0, 255, 255),
+            selection: Color::RGBA(20, 20, 50, 255),
+            highlight: Color::RGBA(255, 170, 0, 255),
+            error: Color::RGBA(200, 90, 90, 255),
+            border_radius: 7,
+            // Fits a title and a subtitle line
+            row_height: 40,
+            prompt_font_size: 24,
+            item_font_size: 14,
+            subtitle_font_size: 11,
+            ui_font_size: 20,
+        }
+    }
+}
+
+impl Theme {
+    pub fn light() -> Theme {
+        Theme {
+            background: Color::RGBA(230, 230, 235, 255),
+            surface: Color::RGBA(250, 250, 250, 255),
+            surface_alt: Color::RGBA(242, 242, 245, 255),
+            foreground: Color::RGBA(30, 30, 40, 255),
+            muted: Color::RGBA(120, 120, 130, 255),
+            accent: Color::RGBA(40, 100, 220, 255),
+            selection: Color::RGBA(210, 225, 250, 255),
+            highlight: Color::RGBA(200, 90, 0, 255),
+            error: Color::RGBA(190, 40, 40, 255),
+            ..Theme::default()
+        }
+    }
+
+    pub fn nord() -> Theme {
+        Theme {
+            background: Color::RGBA(46, 52, 64, 255),
+            surface: Color::RGBA(59, 66, 82, 255),
+            surface_alt: Color::RGBA(67, 76, 94, 255),
+            foreground: Color::RGBA(236, 239, 244, 255),
+            muted: Color::RGBA(129, 161, 193, 255),
+            accent: Color::RGBA(136, 192, 208, 255),
+            selection: Color::RGBA(76, 86, 106, 255),
+            highlight: Color::RGBA(235, 203, 139, 255),
+            error: Color::RGBA(191, 97, 106, 255),
+            border_radius: 4,
+            ..Theme::default()
+        }
+    }
+
+    // Exposed to config.lua as the `themes` table
+    pub fn builtin() -> HashMap<&'static str, Theme> {
+        HashMap::from([
+            ("default", Theme::default()),
+            ("light", Theme::light()),
+            ("nord", Theme::nord()),
+        ])
+    }
+
+    // Alias and point size of every theme font
+    pub fn fonts(&self) -> [(&'static str, u16); 4] {
+        [
+            (PROMPT_FONT, self.prompt_font_size),
+            (ITEM_FONT, self.item_font_size),
+            (SUBTITLE_FONT, self.subtitle_font_size),
+            (UI_FONT, self.ui_font_size),
+        ]
+    }
+}
+
+// Colours are { r = .., g = .., b = .., a = .. } tables in config.lua, see `color(r, g, b, a)`
+pub mod color {
+    use super::*;
+
+    pub fn serialize<S>(color: &Color, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("r", &color.r)?;
+        map.serialize_entry("g", &color.g)?;
+        map.serialize_entry("b", &color.b)?;
+        map.serialize_entry("a", &color.a)?;
+        map.end()
+    }
+
+    pub fn deserialize<'de, D>(des: D) -> Result<Color, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ColorVisitor;
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = Color;
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut r: u8 = 0;
+                let mut g: u8 = 0;
+                let mut b: u8 = 0;
+                // Opaque unless said otherwise
+                let mut a: u8 = 255;
+                while let Some((key, value)) = map.next_entry::<String, u8>()? {
+                    match key.as_str() {
+                        "r" => r = value,
+                        "g" => g = value,
+                        "b" => b = value,
+                        "a" => a = value,
+                        _ => return Err(Error::custom("Unknown key")),
+                    }
+                }
+                Ok(Color::RGBA(r, g, b, a))
+            }
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("Expects a map with 4 keys 'r', 'g', 'b', 'a'")
+            }
+        }
+
+        des.deserialize_map(ColorVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{Lua, LuaSerdeExt};
+
+    use super::*;
+    use crate::config::{set_globals, Config};
+
+    #[test]
+    fn test_theme_from_lua() {
+        let lua = Lua::new();
+        set_globals(&lua);
+        lua.load(
+            "tudo.theme = themes.nord
+            tudo.theme.accent = color(1, 2, 3, 4)
+            tudo.theme.row_height = 48",
+        )
+        .exec()
+        .unwrap();
+        let config: Config = lua.from_value(lua.globals().get("tudo").unwrap()).unwrap();
+
+        assert_eq!(config.theme.accent, Color::RGBA(1, 2, 3, 4));
+        assert_eq!(config.theme.row_height, 48);
+        assert_eq!(config.theme.background, Theme::nord().background);
+
+        // Missing fields and a missing alpha fall back to the defaults
+        lua.load("tudo.theme = { muted = { r = 9, g = 9, b = 9 } }")
+            .exec()
+            .unwrap();
+        let config: Config = lua.from_value(lua.globals().get("tudo").unwrap()).unwrap();
+        assert_eq!(config.theme.muted, Color::RGBA(9, 9, 9, 255));
+        assert_eq!(config.theme.accent, Theme::default().accent);
+    }
+}
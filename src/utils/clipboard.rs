@@ -0,0 +1,34 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+// Clipboard tool of the current session, None when it can't be told
+fn clipboard_command() -> Option<Vec<&'static str>> {
+    match std::env::var("XDG_SESSION_TYPE").ok()?.as_str() {
+        "wayland" => Some(vec!["wl-copy"]),
+        "x11" => Some(vec!["xsel", "--clipboard", "--input"]),
+        _ => None,
+    }
+}
+
+// Hands the text over to wl-copy or xsel, which keep serving it after we're gone
+pub fn set_clipboard(text: &str) -> io::Result<()> {
+    let Some(command) = clipboard_command() else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unknown session type, can't set the clipboard",
+        ));
+    };
+
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    // Reap it in the background, wl-copy forks and exits but xsel may stay around
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
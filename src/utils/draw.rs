@@ -2,13 +2,21 @@ use sdl2::{
     pixels::{Color, PixelFormatEnum},
     rect::Rect,
     render::{BlendMode, Canvas, Texture, TextureCreator},
-    ttf::Font,
     video::{Window, WindowContext},
 };
 
-pub fn draw_string(s: String, canvas: &mut Canvas<Window>, font: &Font, fg: Color, x: i32, y: i32) {
+use super::font::FontSet;
+
+pub fn draw_string(
+    s: String,
+    canvas: &mut Canvas<Window>,
+    font: &FontSet,
+    fg: Color,
+    x: i32,
+    y: i32,
+) {
     let tc = canvas.texture_creator();
-    let surf = font.render(&s).blended(fg).unwrap();
+    let surf = font.render_blended(&s, fg).unwrap();
     let texture = tc.create_texture_from_surface(surf).unwrap();
 
     let query = texture.query();
@@ -20,10 +28,10 @@ pub fn draw_string(s: String, canvas: &mut Canvas<Window>, font: &Font, fg: Colo
 pub fn draw_string_texture<'a>(
     s: String,
     tc: &'a TextureCreator<WindowContext>,
-    font: &Font,
+    font: &FontSet,
     fg: Color,
 ) -> Texture<'a> {
-    let surf = font.render(&s).blended(fg).unwrap();
+    let surf = font.render_blended(&s, fg).unwrap();
     let texture = tc.create_texture_from_surface(surf).unwrap();
     texture
 }
@@ -32,11 +40,11 @@ pub fn draw_string_texture_canvas(
     x: i32,
     y: i32,
     s: String,
-    font: &Font,
+    font: &FontSet,
     fg: Color,
 ) {
     let tc = canvas.texture_creator();
-    let surf = font.render(&s).blended(fg).unwrap();
+    let surf = font.render_blended(&s, fg).unwrap();
     let texture = tc.create_texture_from_surface(surf).unwrap();
     let query = texture.query();
     let (w, h) = (query.width, query.height);
@@ -1,71 +1,365 @@
-use std::{cell::UnsafeCell, collections::HashMap, process::Command};
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    ffi::{c_int, CString},
+    ops::Deref,
+    path::Path,
+    process::Command,
+};
 
-use sdl2::ttf::{Font, Sdl2TtfContext};
+use log::warn;
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::BlendMode,
+    surface::Surface,
+    sys::ttf::{TTF_CloseFont, TTF_Font, TTF_OpenFont},
+    ttf::{Font, FontStyle, Sdl2TtfContext},
+};
 
-#[derive(Eq, Hash, PartialEq, Clone)]
+// SDL_ttf 2.0.18 and later, the sdl2 bindings stop at the 16 bit lookup
+extern "C" {
+    fn TTF_GlyphIsProvided32(font: *mut TTF_Font, ch: u32) -> c_int;
+}
+
+// Size of aliases nobody declared and whose name doesn't end in one, e.g. "normal-20"
+pub const DEFAULT_POINT_SIZE: u16 = 16;
+
+// fc-list doesn't agree on what the plain face is called
+const REGULAR_STYLES: [&str; 4] = ["Regular", "Book", "Normal", "Roman"];
+
+#[derive(Eq, Hash, PartialEq, Clone, Debug)]
 pub struct FontConfig {
     pub alias: String,
+    // Family name as listed by fc-list, or a path to a font file
     pub family: String,
     pub point_size: u16,
+    pub style: String,
 }
 
-pub struct FontManager<'a> {
-    pub ttf: &'a Sdl2TtfContext,
-    file_map: HashMap<String, String>,
-    pub cache: UnsafeCell<HashMap<String, Font<'a, 'a>>>,
+impl FontConfig {
+    pub fn new(alias: impl AsRef<str>, family: impl AsRef<str>, point_size: u16) -> FontConfig {
+        FontConfig {
+            alias: alias.as_ref().to_string(),
+            family: family.as_ref().to_string(),
+            point_size,
+            style: "Regular".to_string(),
+        }
+    }
 }
 
-fn process_font(
-    file_map: HashMap<String, String>,
-    fconfig: FontConfig,
-    ttf: &Sdl2TtfContext,
-) -> Font<'_, '_> {
-    let path = file_map.get(&fconfig.family).unwrap();
-    ttf.load_font(path, fconfig.point_size).unwrap()
+// The font file opened a second time, sdl2 doesn't hand out the handle a Font owns and its
+// own glyph lookup can't see past U+FFFF, where emoji and Nerd Font icons live
+struct Glyphs(*mut TTF_Font);
+
+impl Glyphs {
+    fn open(path: &str, point_size: u16) -> Option<Glyphs> {
+        let path = CString::new(path).ok()?;
+        let raw = unsafe { TTF_OpenFont(path.as_ptr(), point_size as c_int) };
+        (!raw.is_null()).then_some(Glyphs(raw))
+    }
+
+    fn has(&self, ch: char) -> bool {
+        unsafe { TTF_GlyphIsProvided32(self.0, ch as u32) != 0 }
+    }
 }
 
-impl<'a> FontManager<'_> {
-    pub fn new(ttf: &'a Sdl2TtfContext) -> FontManager<'a> {
-        let mut file_map: HashMap<String, String> = HashMap::new();
-        let fc_list = Command::new("fc-list").output();
+impl Drop for Glyphs {
+    fn drop(&mut self) {
+        unsafe { TTF_CloseFont(self.0) }
+    }
+}
 
-        for line in String::from_utf8(fc_list.unwrap().stdout).unwrap().lines() {
-            if !line.contains("style=Regular") {
-                continue;
+pub struct Face<'a> {
+    pub font: Font<'a, 'a>,
+    glyphs: Option<Glyphs>,
+}
+
+impl Face<'_> {
+    fn has_glyph(&self, ch: char) -> bool {
+        match &self.glyphs {
+            Some(glyphs) => glyphs.has(ch),
+            None => u16::try_from(ch as u32).is_ok() && self.font.find_glyph(ch).is_some(),
+        }
+    }
+}
+
+// A font and the fonts its missing glyphs are taken from, in order
+pub struct FontSet<'a> {
+    pub primary: Face<'a>,
+    pub fallbacks: Vec<Face<'a>>,
+}
+
+// Everything that measures text keeps using the primary font
+impl<'a> Deref for FontSet<'a> {
+    type Target = Font<'a, 'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.primary.font
+    }
+}
+
+impl<'a> FontSet<'a> {
+    // Splits text into runs of characters drawn with the same font
+    pub fn runs<'b>(&'b self, text: &str) -> Vec<(&'b Font<'a, 'a>, String)> {
+        let mut runs: Vec<(&Font, String)> = Vec::new();
+        for ch in text.chars() {
+            let face = match ch.is_whitespace() || ch.is_control() || self.primary.has_glyph(ch) {
+                true => &self.primary,
+                false => self
+                    .fallbacks
+                    .iter()
+                    .find(|face| face.has_glyph(ch))
+                    .unwrap_or(&self.primary),
+            };
+            let font = &face.font;
+            match runs.last_mut() {
+                Some((last, run)) if std::ptr::eq(*last, font) => run.push(ch),
+                _ => runs.push((font, ch.to_string())),
             }
-            let split = line.split(":").collect::<Vec<&str>>();
-            let path = split.first().unwrap();
-            let family_names = split.get(1).unwrap();
-            for family in family_names.split(",") {
-                file_map.insert(family.trim().to_string(), path.to_string());
+        }
+        runs
+    }
+
+    pub fn render_blended(&self, text: &str, fg: Color) -> Result<Surface<'static>, String> {
+        let runs = self.runs(text);
+        if runs.len() <= 1 {
+            return self
+                .primary
+                .font
+                .render(text)
+                .blended(fg)
+                .map_err(|e| e.to_string());
+        }
+
+        // Render every run on its own and line them up on the tallest baseline
+        let ascent = runs
+            .iter()
+            .map(|(font, _)| font.ascent())
+            .max()
+            .unwrap_or(0);
+        let mut surfaces = Vec::new();
+        for (font, run) in runs {
+            let mut surface = font.render(&run).blended(fg).map_err(|e| e.to_string())?;
+            surface.set_blend_mode(BlendMode::None)?;
+            surfaces.push((ascent - font.ascent(), surface));
+        }
+
+        let width = surfaces.iter().map(|(_, s)| s.width()).sum();
+        let height = surfaces
+            .iter()
+            .map(|(y, s)| *y as u32 + s.height())
+            .max()
+            .unwrap_or(0);
+        let mut target = Surface::new(width, height, PixelFormatEnum::ARGB8888)?;
+        let mut x = 0;
+        for (y, surface) in surfaces {
+            let rect = Rect::new(x, y, surface.width(), surface.height());
+            surface.blit(None, &mut target, rect)?;
+            x += surface.width() as i32;
+        }
+        Ok(target)
+    }
+}
+
+pub struct FontManager<'a> {
+    pub ttf: &'a Sdl2TtfContext,
+    // Family to style to file
+    file_map: HashMap<String, HashMap<String, String>>,
+    family: String,
+    fallbacks: Vec<String>,
+    declared: HashMap<String, FontConfig>,
+    // Boxed so loading another font can't move the ones already handed out
+    pub cache: UnsafeCell<HashMap<String, Box<FontSet<'a>>>>,
+}
+
+fn list_fonts() -> HashMap<String, HashMap<String, String>> {
+    let mut file_map: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let output = match Command::new("fc-list")
+        .arg("--format=%{file}:%{family}:%{style}\n")
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            warn!("Can't list fonts with fc-list: {}", err);
+            return file_map;
+        }
+    };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut split = line.splitn(3, ':');
+        let (Some(path), Some(families), styles) = (split.next(), split.next(), split.next())
+        else {
+            continue;
+        };
+        for family in families.split(',') {
+            let entry = file_map.entry(family.trim().to_string()).or_default();
+            for style in styles.unwrap_or("Regular").split(',') {
+                entry
+                    .entry(style.trim().to_string())
+                    .or_insert(path.to_string());
             }
         }
+    }
+    file_map
+}
+
+// Alias named after its size, e.g. "normal-20"
+fn parse_alias_size(alias: &str) -> Option<u16> {
+    alias.rsplit_once('-')?.1.parse().ok()
+}
+
+impl<'a> FontManager<'a> {
+    pub fn new(ttf: &'a Sdl2TtfContext) -> FontManager<'a> {
         FontManager {
-            file_map,
+            file_map: list_fonts(),
             ttf,
+            family: String::new(),
+            fallbacks: Vec::new(),
+            declared: HashMap::new(),
             cache: HashMap::new().into(),
         }
     }
 
-    pub fn get_font(&'a self, alias: impl AsRef<str>) -> &'a Font<'a, 'a> {
+    // Fonts are loaded the first time they are asked for, changing this drops the loaded ones
+    pub fn configure(
+        &mut self,
+        family: impl AsRef<str>,
+        fallbacks: Vec<String>,
+        declared: HashMap<String, FontConfig>,
+    ) {
+        self.family = family.as_ref().to_string();
+        self.fallbacks = fallbacks;
+        self.declared = declared;
+        self.cache.get_mut().clear();
+    }
+
+    // Picks the file for a family and style, the plain face if there's no such style
+    fn find_file(&self, family: &str, style: &str) -> Option<(String, bool)> {
+        if Path::new(family).is_file() {
+            let exact = REGULAR_STYLES.iter().any(|s| s.eq_ignore_ascii_case(style));
+            return Some((family.to_string(), exact));
+        }
+        let styles = self.file_map.get(family)?;
+        if let Some((_, path)) = styles.iter().find(|(s, _)| s.eq_ignore_ascii_case(style)) {
+            return Some((path.clone(), true));
+        }
+        REGULAR_STYLES
+            .iter()
+            .find_map(|s| styles.get(*s))
+            .or_else(|| styles.values().next())
+            .map(|path| (path.clone(), false))
+    }
+
+    fn open(&self, path: &str, config: &FontConfig, exact: bool) -> Option<Face<'a>> {
+        let mut font = match self.ttf.load_font(path, config.point_size) {
+            Ok(font) => font,
+            Err(err) => {
+                warn!("Can't load font {}: {}", path, err);
+                return None;
+            }
+        };
+        // Without a file for the style let SDL fake it
+        if !exact {
+            let style = config.style.to_lowercase();
+            let mut font_style = FontStyle::NORMAL;
+            if style.contains("bold") {
+                font_style |= FontStyle::BOLD;
+            }
+            if style.contains("italic") || style.contains("oblique") {
+                font_style |= FontStyle::ITALIC;
+            }
+            font.set_style(font_style);
+        }
+        Some(Face {
+            font,
+            glyphs: Glyphs::open(path, config.point_size),
+        })
+    }
+
+    fn process_font(&self, config: &FontConfig) -> FontSet<'a> {
+        let mut families = vec![config.family.clone()];
+        if !self.family.is_empty() && self.family != config.family {
+            families.push(self.family.clone());
+        }
+        families.extend(self.fallbacks.iter().cloned());
+
+        let mut loaded = families.iter().filter_map(|family| {
+            let (path, exact) = self.find_file(family, &config.style)?;
+            self.open(&path, config, exact)
+        });
+
+        let primary = match loaded.next() {
+            Some(font) => font,
+            // Anything is better than no text at all
+            None => self
+                .file_map
+                .values()
+                .flat_map(|styles| styles.values())
+                .find_map(|path| self.open(path, config, true))
+                .expect("No usable font installed"),
+        };
+        if !self.file_map.contains_key(&config.family) && !Path::new(&config.family).is_file() {
+            warn!(
+                "Font family '{}' not found for '{}'",
+                config.family, config.alias
+            );
+        }
+
+        FontSet {
+            primary,
+            fallbacks: loaded.collect(),
+        }
+    }
+}
+
+impl<'a> FontManager<'_> {
+    pub fn get_font(&'a self, alias: impl AsRef<str>) -> &'a FontSet<'a> {
+        let alias = alias.as_ref();
         let map = self.cache.get();
 
-        unsafe { (*map).get(alias.as_ref()).unwrap() }
+        if let Some(font) = unsafe { (*map).get(alias) } {
+            return font;
+        }
+
+        let config = self.declared.get(alias).cloned().unwrap_or_else(|| {
+            FontConfig::new(
+                alias,
+                &self.family,
+                parse_alias_size(alias).unwrap_or(DEFAULT_POINT_SIZE),
+            )
+        });
+        self.load_font(FontConfig {
+            alias: alias.to_string(),
+            ..config
+        })
     }
 
     // Use interior mutability in order to have a shared reference &self be able to mutate the
     // inner hashmap
-    pub fn load_font(&'a self, font_config: FontConfig) -> &'a Font<'a, 'a> {
-        let key = font_config.clone();
+    pub fn load_font(&'a self, font_config: FontConfig) -> &'a FontSet<'a> {
+        let key = font_config.alias.clone();
 
         // SAFETY this is pulled from FrozenMap implementation at https://docs.rs/elsa/latest/src/elsa/map.rs.html#74
         // Still not sure how this works
         let ret = unsafe {
             let map = self.cache.get();
-            &*(*map)
-                .entry(key.alias)
-                .or_insert_with(|| process_font(self.file_map.clone(), font_config, self.ttf))
+            &**(*map)
+                .entry(key)
+                .or_insert_with(|| Box::new(self.process_font(&font_config)))
         };
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_alias_size() {
+        assert_eq!(parse_alias_size("normal-20"), Some(20));
+        assert_eq!(parse_alias_size("prompt"), None);
+        assert_eq!(parse_alias_size("my-font"), None);
+    }
+}
@@ -1,8 +1,202 @@
+// Scoring constants, loosely based on fzf's
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL: i32 = 7;
+const BONUS_CONSECUTIVE: i32 = 4;
+const BONUS_PREFIX: i32 = 2;
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
 #[derive(Debug)]
 pub struct FuzzyMatch {
     pub value: String,
-    pub score: u32,
+    pub score: i32,
     pub original_idx: usize,
+    // Char indices of `value` that matched the filter
+    pub positions: Vec<usize>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    NonWord,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_lowercase() {
+        CharClass::Lower
+    } else if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Digit
+    } else if c.is_alphabetic() {
+        // Scripts without case
+        CharClass::Lower
+    } else {
+        CharClass::NonWord
+    }
+}
+
+// Bonus for matching a character given the class of the one before it
+fn position_bonus(prev: CharClass, cur: CharClass) -> i32 {
+    match (prev, cur) {
+        (_, CharClass::NonWord) => 0,
+        (CharClass::NonWord, _) => BONUS_BOUNDARY,
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL,
+        (CharClass::Lower | CharClass::Upper, CharClass::Digit) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// Case insensitive subsequence match. Returns None when `pattern` is not a subsequence of
+// `candidate`, otherwise the score of the best alignment. Matches on word boundaries, camelCase
+// humps, consecutive runs and at the start of the candidate are rewarded, gaps are penalized.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<i32> {
+    fuzzy_match_positions(pattern, candidate).map(|(score, _)| score)
+}
+
+// Same as `fuzzy_match` but also returns the char indices of `candidate` used by the best
+// alignment
+pub fn fuzzy_match_positions(pattern: &str, candidate: &str) -> Option<(i32, Vec<usize>)> {
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let lowered: Vec<char> = chars.iter().map(|c| lower(*c)).collect();
+
+    if pattern.is_empty() {
+        return Some((0, vec![]));
+    }
+
+    // Cheap subsequence check before doing the full scoring
+    let mut pidx = 0;
+    for c in lowered.iter() {
+        if pidx < pattern.len() && *c == pattern[pidx] {
+            pidx += 1;
+        }
+    }
+    if pidx < pattern.len() {
+        return None;
+    }
+
+    let (n, m) = (pattern.len(), chars.len());
+
+    let bonus: Vec<i32> = (0..m)
+        .map(|j| match j {
+            0 => BONUS_BOUNDARY + BONUS_PREFIX,
+            _ => position_bonus(char_class(chars[j - 1]), char_class(chars[j])),
+        })
+        .collect();
+
+    // score[i][j]: best score for pattern[..=i] with pattern[i] matched at candidate[j]
+    // run[i][j]: length of the consecutive run ending at that match and the bonus it carries
+    // from[i][j]: column where pattern[i - 1] was matched, used to trace back the positions
+    let mut score: Vec<Vec<Option<i32>>> = vec![vec![None; m]; n];
+    let mut run: Vec<Vec<(usize, i32)>> = vec![vec![(0, 0); m]; n];
+    let mut from: Vec<Vec<usize>> = vec![vec![0; m]; n];
+
+    for i in 0..n {
+        // Best score of the previous row, already paying for a gap up to the current column,
+        // and the column it came from
+        let mut best_with_gap: Option<(i32, usize)> = None;
+
+        for j in 0..m {
+            if i > 0 && j > 1 {
+                let extended = best_with_gap.map(|(a, k)| (a + SCORE_GAP_EXTENSION, k));
+                let started = score[i - 1][j - 2].map(|b| (b + SCORE_GAP_START, j - 2));
+                best_with_gap = match (extended, started) {
+                    (Some(a), Some(b)) if b.0 > a.0 => Some(b),
+                    (Some(a), _) => Some(a),
+                    (None, b) => b,
+                };
+            }
+
+            if lowered[j] != pattern[i] {
+                continue;
+            }
+
+            if i == 0 {
+                score[i][j] = Some(
+                    SCORE_MATCH
+                        + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER
+                        + match j {
+                            0 => 0,
+                            _ => SCORE_GAP_START + (j as i32 - 1) * SCORE_GAP_EXTENSION,
+                        },
+                );
+                run[i][j] = (1, bonus[j]);
+                continue;
+            }
+
+            // Either extend a consecutive run...
+            let consecutive = match j {
+                0 => None,
+                _ => score[i - 1][j - 1].map(|s| {
+                    let (len, run_bonus) = run[i - 1][j - 1];
+                    let b = *[bonus[j], run_bonus, BONUS_CONSECUTIVE]
+                        .iter()
+                        .max()
+                        .unwrap();
+                    (s + SCORE_MATCH + b, (len + 1, b), j - 1)
+                }),
+            };
+            // ... or start a new one after a gap
+            let gapped = best_with_gap.map(|(s, k)| (s + SCORE_MATCH + bonus[j], (1, bonus[j]), k));
+
+            let best = match (consecutive, gapped) {
+                (Some(c), Some(g)) if g.0 > c.0 => Some(g),
+                (Some(c), _) => Some(c),
+                (None, g) => g,
+            };
+            if let Some((s, r, k)) = best {
+                score[i][j] = Some(s);
+                run[i][j] = r;
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, best) = score[n - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, s)| s.map(|s| (j, s)))
+        .max_by_key(|(_, s)| *s)?;
+
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+
+    Some((best, positions))
+}
+
+pub fn fuzzy(filter: String, candidates: &[String]) -> Option<Vec<FuzzyMatch>> {
+    let mut result: Vec<FuzzyMatch> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            fuzzy_match_positions(&filter, c).map(|(score, positions)| FuzzyMatch {
+                value: c.to_string(),
+                score,
+                original_idx: i,
+                positions,
+            })
+        })
+        .collect();
+
+    // Best match first
+    result.sort_by_key(|m| std::cmp::Reverse(m.score));
+
+    match result.len() {
+        0 => None,
+        _ => Some(result),
+    }
 }
 
 pub fn basic(filter: String, candidates: &[String]) -> Option<Vec<FuzzyMatch>> {
@@ -14,6 +208,7 @@ pub fn basic(filter: String, candidates: &[String]) -> Option<Vec<FuzzyMatch>> {
             value: c.to_string(),
             score: 0,
             original_idx: i,
+            positions: vec![],
         })
         .collect();
 
@@ -32,6 +227,7 @@ pub fn basic_contains(filter: String, candidates: &[String]) -> Option<Vec<Fuzzy
             value: c.to_string(),
             score: 0,
             original_idx: i,
+            positions: vec![],
         })
         .collect();
 
@@ -41,10 +237,9 @@ pub fn basic_contains(filter: String, candidates: &[String]) -> Option<Vec<Fuzzy
     }
 }
 
-// fn fuzzy(filter: String, candidates: [String]) -> [FuzzyMatch] {}
 #[cfg(test)]
 mod tests {
-    use crate::utils::fuzzy::basic;
+    use crate::utils::fuzzy::{basic, fuzzy, fuzzy_match, fuzzy_match_positions};
     #[test]
     fn test_basic_search() {
         dbg!("hhhhhhhheeelo");
@@ -53,4 +248,62 @@ mod tests {
             &["hello".into(), "foo".into(), "hello world".into()]
         ));
     }
+
+    #[test]
+    fn test_fuzzy_subsequence() {
+        assert!(fuzzy_match("ffx", "Firefox").is_some());
+        assert!(fuzzy_match("FFX", "firefox").is_some());
+        assert!(fuzzy_match("xff", "Firefox").is_none());
+        assert_eq!(fuzzy_match("", "Firefox"), Some(0));
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        let candidates: Vec<String> = vec![
+            "Audio Effects".into(),
+            "Firefox".into(),
+            "Files".into(),
+            "Xfce Terminal".into(),
+        ];
+        let matches = fuzzy(String::from("ffx"), &candidates).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].value, "Firefox");
+
+        // Prefix and word boundary matches beat matches in the middle of words
+        let candidates: Vec<String> = vec!["Visual Studio Code".into(), "Vscode".into()];
+        let matches = fuzzy(String::from("code"), &candidates).unwrap();
+        assert_eq!(matches[0].value, "Visual Studio Code");
+
+        let candidates: Vec<String> = vec!["steam".into(), "Terminal".into()];
+        let matches = fuzzy(String::from("te"), &candidates).unwrap();
+        assert_eq!(matches[0].value, "Terminal");
+    }
+
+    #[test]
+    fn test_fuzzy_camel_case() {
+        let camel = fuzzy_match("gs", "GitStatus").unwrap();
+        let flat = fuzzy_match("gs", "Gitstatus").unwrap();
+        assert!(camel > flat);
+    }
+
+    #[test]
+    fn test_fuzzy_consecutive() {
+        let consecutive = fuzzy_match("fire", "Firefox").unwrap();
+        let spread = fuzzy_match("fire", "Fancy Icon Reader Extra").unwrap();
+        assert!(consecutive > spread);
+    }
+
+    #[test]
+    fn test_fuzzy_positions() {
+        assert_eq!(
+            fuzzy_match_positions("ffx", "Firefox").unwrap().1,
+            vec![0, 4, 6]
+        );
+        // Word boundaries win over earlier matches in the middle of a word
+        assert_eq!(
+            fuzzy_match_positions("sc", "Visual Studio Code").unwrap().1,
+            vec![7, 14]
+        );
+        assert!(fuzzy_match_positions("", "Firefox").unwrap().1.is_empty());
+    }
 }
@@ -1,6 +1,9 @@
+use log::warn;
+use resvg::{tiny_skia, usvg};
 use sdl2::{
     image::LoadTexture,
-    render::{Texture, TextureCreator},
+    pixels::PixelFormatEnum,
+    render::{BlendMode, Texture, TextureCreator},
     video::WindowContext,
 };
 
@@ -10,19 +13,64 @@ use std::{collections::HashMap, fs};
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub struct ImageKey {
     pub path: String,
+    // Only set for scalable images, raster images are loaded once at their own size
+    pub size: Option<u32>,
 }
 
 pub struct ImageCache<'fa> {
-    pub cache: UnsafeCell<HashMap<ImageKey, Texture<'fa>>>,
+    // Images that failed to load are kept as None, so they are only tried once
+    pub cache: UnsafeCell<HashMap<ImageKey, Option<Texture<'fa>>>>,
     tc: &'fa TextureCreator<WindowContext>,
 }
 
-fn gen_tex(path: String, tc: &TextureCreator<WindowContext>) -> Texture<'_> {
-    let buf = fs::read(&path).unwrap();
+fn is_svg(path: &str) -> bool {
+    path.ends_with(".svg") || path.ends_with(".svgz")
+}
+
+fn gen_tex<'a>(path: &str, tc: &'a TextureCreator<WindowContext>) -> Result<Texture<'a>, String> {
+    let buf = fs::read(path).map_err(|e| e.to_string())?;
+
+    tc.load_texture_bytes(&buf)
+}
+
+// Rasterize an SVG into a size x size texture, keeping the aspect ratio and centering it
+fn gen_svg_tex<'a>(
+    path: &str,
+    size: u32,
+    tc: &'a TextureCreator<WindowContext>,
+) -> Result<Texture<'a>, String> {
+    let buf = fs::read(path).map_err(|e| e.to_string())?;
+    let tree = usvg::Tree::from_data(&buf, &usvg::Options::default()).map_err(|e| e.to_string())?;
 
-    let tex: Texture = tc.load_texture_bytes(&buf).unwrap();
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(
+        (size as f32 - svg_size.width() * scale) / 2.0,
+        (size as f32 - svg_size.height() * scale) / 2.0,
+    );
 
-    tex
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size, size).ok_or(format!("can't rasterize at size {}", size))?;
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia works with premultiplied alpha, SDL blending expects straight alpha
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    let mut tex = tc
+        .create_texture_static(PixelFormatEnum::ABGR8888, size, size)
+        .map_err(|e| e.to_string())?;
+    tex.update(None, &pixels, size as usize * 4)
+        .map_err(|e| e.to_string())?;
+    tex.set_blend_mode(BlendMode::Blend);
+
+    Ok(tex)
 }
 
 impl<'fa> ImageCache<'fa> {
@@ -33,21 +81,34 @@ impl<'fa> ImageCache<'fa> {
         }
     }
 
+    pub fn get_image(&self, path: impl AsRef<str>) -> Option<&Texture> {
+        // SVGs have no size of their own, 32 is the usual icon size
+        self.get_image_with_size(path, 32)
+    }
+
     // Use interior mutability in order to have a shared reference &self be able to mutate the
-    // inner hashmap
-    pub fn get_image(&self, path: impl AsRef<str>) -> &Texture {
+    // inner hashmap. None if the image can't be loaded, e.g. a broken SVG in an icon theme
+    pub fn get_image_with_size(&self, path: impl AsRef<str>, size: u32) -> Option<&Texture> {
+        let path = path.as_ref().to_string();
+        let svg = is_svg(&path);
         let key = ImageKey {
-            path: path.as_ref().to_string(),
+            path: path.clone(),
+            size: svg.then_some(size),
         };
 
         // SAFETY this is pulled from FrozenMap implementation at https://docs.rs/elsa/latest/src/elsa/map.rs.html#74
         // Still not sure how this works
         let ret = unsafe {
             let map = self.cache.get();
-            &*(*map)
-                .entry(key)
-                .or_insert_with(|| gen_tex(path.as_ref().to_string(), self.tc))
+            &*(*map).entry(key).or_insert_with(|| {
+                let tex = match svg {
+                    true => gen_svg_tex(&path, size, self.tc),
+                    false => gen_tex(&path, self.tc),
+                };
+                tex.inspect_err(|err| warn!("Can't load image {}: {}", path, err))
+                    .ok()
+            })
         };
-        ret
+        ret.as_ref()
     }
 }
@@ -4,6 +4,11 @@ use sdl2::{
     rect::{Point, Rect},
 };
 
+// Single quoted for `sh -c`, quotes inside are closed, escaped and reopened
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "'\\''"))
+}
+
 pub fn ignore_numlock(event: &Event) -> Event {
     match event {
         sdl2::event::Event::KeyDown {
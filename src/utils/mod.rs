@@ -1,5 +1,6 @@
 pub mod atlas;
 pub mod cache;
+pub mod clipboard;
 pub mod draw;
 pub mod font;
 pub mod fuzzy;
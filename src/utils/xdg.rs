@@ -1,251 +1,757 @@
 use std::{
     collections::{HashMap, HashSet},
+    fmt::Display,
     fs,
+    str::FromStr,
 };
 
-use log::debug;
+use log::{debug, warn};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IniError {
+    Io(String),
+    Parse { line: usize, message: String },
+}
+
+impl Display for IniError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IniError::Io(err) => write!(f, "{}", err),
+            IniError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for IniError {}
+
+// Desktop Entry style key file, as used by .desktop files, index.theme, settings.ini and
+// mimeapps.list
+#[derive(Debug, Default, Clone)]
+pub struct IniFile {
+    groups: HashMap<String, Section>,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Section {
     pub header: String,
     pub values: HashMap<String, String>,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub struct IconConfig {
-    pub size: u32,
-    pub name: String,
+pub fn parse_ini_file(path: impl AsRef<str>) -> Result<IniFile, IniError> {
+    let contents = std::fs::read_to_string(path.as_ref())
+        .map_err(|err| IniError::Io(format!("{}: {}", path.as_ref(), err)))?;
+
+    parse_ini(&contents)
 }
 
-impl IconConfig {
-    pub fn new(size: u32, name: String) -> Self {
-        Self { size, name }
-    }
+pub fn parse_ini(contents: &str) -> Result<IniFile, IniError> {
+    parse_ini_with(contents, false)
 }
 
-pub struct IconFinder {
-    map: HashMap<IconConfig, String>,
-    sizes: HashSet<u32>,
+// For files that only look like key files, e.g. kdeglobals with its [Colors:Header][Inactive]
+// groups. Groups and lines that don't parse are skipped instead of failing the whole file
+pub fn parse_ini_lenient(contents: &str) -> IniFile {
+    parse_ini_with(contents, true).unwrap_or_default()
 }
-impl Default for IconFinder {
-    fn default() -> Self {
-        Self::new()
+
+fn parse_ini_with(contents: &str, lenient: bool) -> Result<IniFile, IniError> {
+    let mut res = IniFile::default();
+    let mut current: Option<String> = None;
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with("#") {
+            continue;
+        }
+
+        match parse_line(line, &mut res, &mut current) {
+            Ok(()) => (),
+            // Keys of a group we couldn't read don't belong to the previous one
+            Err(_) if lenient && line.starts_with("[") => current = None,
+            Err(_) if lenient => (),
+            Err(message) => {
+                return Err(IniError::Parse {
+                    line: idx + 1,
+                    message: message.to_string(),
+                })
+            }
+        }
     }
+
+    Ok(res)
 }
 
-impl IconFinder {
-    pub fn new() -> IconFinder {
-        let (map, sizes) = generate_map();
-        IconFinder { map, sizes }
+fn parse_line(
+    line: &str,
+    res: &mut IniFile,
+    current: &mut Option<String>,
+) -> Result<(), &'static str> {
+    if let Some(header) = line.strip_prefix("[") {
+        let header = header
+            .strip_suffix("]")
+            .ok_or("Unterminated group header")?;
+        if header.contains(['[', ']']) {
+            return Err("Invalid group header");
+        }
+
+        // Repeated groups are not allowed by the spec, merge them instead of failing
+        res.groups
+            .entry(header.to_string())
+            .or_insert_with(|| Section {
+                header: header.to_string(),
+                values: HashMap::new(),
+            });
+        *current = Some(header.to_string());
+    } else {
+        let (key, value) = line.split_once("=").ok_or("Expected a key=value pair")?;
+        let header = current
+            .as_ref()
+            .ok_or("Key found before any group header")?;
+
+        res.groups
+            .get_mut(header)
+            .unwrap()
+            .values
+            .insert(key.trim().to_string(), value.trim().to_string());
     }
-    pub fn get_icon(&self, name: String) -> Option<String> {
-        let candidate: String;
 
-        // First check if icon identifier is a path
-        if name.starts_with("/") && fs::metadata(name.clone()).is_ok() {
-            candidate = name.clone();
-        } else {
-            let icon_config = IconConfig { name, size: 32 };
-            let opt = self.map.get(&icon_config);
-            opt?;
-            candidate = self.map.get(&icon_config).unwrap().to_string();
-        }
+    Ok(())
+}
 
-        // Check if candidate is indeed a file
-        match std::fs::read(&candidate) {
-            Ok(_) => Some(candidate),
-            Err(_) => None,
+// Unescape \s \n \t \r and \\ in string values
+fn unescape(value: &str) -> String {
+    let mut res = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => res.push(' '),
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some('\\') => res.push('\\'),
+            Some(other) => {
+                res.push('\\');
+                res.push(other);
+            }
+            None => res.push('\\'),
         }
     }
+    res
+}
 
-    pub fn get_icon_with_size(&self, name: String, size: u32) -> Option<String> {
-        fn check_file(path: String) -> Option<String> {
-            match std::fs::read(&path) {
-                Ok(_) => Some(path),
-                Err(_) => None,
-            }
+// Split on `separator` unless it is escaped, then unescape every element
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == separator => current.push(separator),
+                Some(next) => {
+                    current.push('\\');
+                    current.push(next);
+                }
+                None => current.push('\\'),
+            },
+            c if c == separator => res.push(std::mem::take(&mut current)),
+            c => current.push(c),
         }
+    }
+    res.push(current);
 
-        // First check if icon identifier is a path
-        if name.starts_with("/") && fs::metadata(name.clone()).is_ok() {
-            return check_file(name);
+    res.iter()
+        .map(|element| unescape(element.trim()))
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+// Locale variants to try for localized keys, most specific first.
+// "pt_BR.UTF-8@euro" gives pt_BR@euro, pt_BR, pt@euro, pt
+pub fn locale_variants(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once("@") {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split(".").next().unwrap();
+    let (lang, country) = match rest.split_once("_") {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    if !lang.is_empty() && lang != "C" && lang != "POSIX" {
+        variants.push(lang.to_string());
+    }
+    variants
+}
+
+pub fn current_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+impl IniFile {
+    pub fn group(&self, header: &str) -> Option<&Section> {
+        self.groups.get(header)
+    }
+    pub fn groups(&self) -> impl Iterator<Item = &Section> {
+        self.groups.values()
+    }
+}
+
+impl Section {
+    // Raw value, as written in the file
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.values.get(key)
+    }
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        self.get(key).map(|value| unescape(value))
+    }
+    // `locales` as returned by `locale_variants`
+    pub fn get_localestring(&self, key: &str, locales: &[String]) -> Option<String> {
+        locales
+            .iter()
+            .find_map(|locale| self.get_string(&format!("{}[{}]", key, locale)))
+            .or_else(|| self.get_string(key))
+    }
+    pub fn get_boolean(&self, key: &str) -> Option<bool> {
+        match self.get(key).map(|value| value.as_str()) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
         }
+    }
+    pub fn get_number<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.parse().ok())
+    }
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        self.get_list_by(key, ';')
+    }
+    // Some files, e.g. index.theme, use commas instead of semicolons
+    pub fn get_list_by(&self, key: &str, separator: char) -> Vec<String> {
+        self.get(key)
+            .map(|value| split_list(value, separator))
+            .unwrap_or_default()
+    }
+    pub fn get_locale_list(&self, key: &str, locales: &[String]) -> Vec<String> {
+        locales
+            .iter()
+            .find_map(|locale| self.get(&format!("{}[{}]", key, locale)))
+            .or_else(|| self.get(key))
+            .map(|value| split_list(value, ';'))
+            .unwrap_or_default()
+    }
+}
+
+// Icon lookup following the freedesktop Icon Theme spec
+// https://specifications.freedesktop.org/icon-theme-spec/latest/
+
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
 
-        // Check for exact match
-        if let Some(path) = self.map.get(&IconConfig {
-            name: name.clone(),
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconDirectory {
+    pub path: String,
+    pub size: u32,
+    // Icons are drawn one pixel per unit, directories of @2x icons only count by their pixel size
+    pub scale: u32,
+    pub min_size: u32,
+    pub max_size: u32,
+    pub threshold: u32,
+    pub kind: DirectoryType,
+}
+
+impl IconDirectory {
+    pub fn from_section(section: &Section) -> Option<IconDirectory> {
+        let size = section.get_number("Size")?;
+        let kind = match section.get_string("Type").as_deref() {
+            Some("Fixed") => DirectoryType::Fixed,
+            Some("Scalable") => DirectoryType::Scalable,
+            _ => DirectoryType::Threshold,
+        };
+
+        Some(IconDirectory {
+            path: section.header.clone(),
             size,
-        }) {
-            return check_file(path.to_string());
-        }
-
-        // Scan different sizes until one appears
-        // In this case we are going from largest to smallest
-        // FIXME(quadrado): We can do better here to try and find the closest match
-        let mut _sizes: Vec<u32> = self.sizes.clone().into_iter().collect::<Vec<u32>>();
-        _sizes.sort();
-        _sizes.reverse();
-
-        for _size in _sizes {
-            let icon_config = IconConfig {
-                name: name.clone(),
-                size: _size,
-            };
-            if let Some(path) = self.map.get(&icon_config) {
-                return check_file(path.to_string());
+            scale: section.get_number("Scale").unwrap_or(1),
+            min_size: section.get_number("MinSize").unwrap_or(size),
+            max_size: section.get_number("MaxSize").unwrap_or(size),
+            threshold: section.get_number("Threshold").unwrap_or(2),
+            kind,
+        })
+    }
+
+    pub fn matches_size(&self, size: u32) -> bool {
+        if self.scale != 1 {
+            return false;
+        }
+        match self.kind {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => self.min_size <= size && size <= self.max_size,
+            DirectoryType::Threshold => {
+                self.size.saturating_sub(self.threshold) <= size
+                    && size <= self.size + self.threshold
             }
         }
-        None
+    }
+
+    pub fn size_distance(&self, size: u32) -> u32 {
+        let (min, max) = match self.kind {
+            DirectoryType::Fixed => (self.size, self.size),
+            DirectoryType::Scalable => (self.min_size, self.max_size),
+            DirectoryType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+        let (min, max) = (min * self.scale, max * self.scale);
+
+        if size < min {
+            min - size
+        } else {
+            size.saturating_sub(max)
+        }
     }
 }
 
-type IniMap = HashMap<String, HashMap<String, String>>;
-pub fn parse_ini_file(path: String) -> Result<IniMap, ()> {
-    let contents = match std::fs::read(path) {
-        Ok(c) => Ok(String::from_utf8(c).unwrap()),
-        Err(_) => return Err(()),
-    };
+#[derive(Debug, Default)]
+pub struct IconTheme {
+    pub name: String,
+    pub parents: Vec<String>,
+    pub directories: Vec<IconDirectory>,
+    // Icon name to (index in `directories`, file path)
+    pub icons: HashMap<String, Vec<(usize, String)>>,
+}
 
-    let mut header_title = "";
-    let mut res: HashMap<String, HashMap<String, String>> = HashMap::new();
+impl IconTheme {
+    // The theme may be spread over several base dirs, the first index.theme found describes it
+    pub fn load(name: &str, base_dirs: &[String]) -> Option<IconTheme> {
+        let index = base_dirs.iter().find_map(|base_dir| {
+            let path = format!("{}/{}/index.theme", base_dir, name);
+            match parse_ini_file(&path) {
+                Ok(ini) => Some(ini),
+                Err(err @ IniError::Parse { .. }) => {
+                    warn!("Could not parse {}: {}", path, err);
+                    None
+                }
+                Err(_) => None,
+            }
+        })?;
+        let theme_section = index.group("Icon Theme")?;
+
+        let directories: Vec<IconDirectory> = theme_section
+            .get_list_by("Directories", ',')
+            .iter()
+            .filter_map(|dir| match index.group(dir) {
+                Some(section) => IconDirectory::from_section(section),
+                None => {
+                    debug!("Section {} not found", dir);
+                    None
+                }
+            })
+            .collect();
+
+        let mut parents = theme_section.get_list_by("Inherits", ',');
+        if name != "hicolor" && !parents.contains(&"hicolor".to_string()) {
+            parents.push("hicolor".to_string());
+        }
 
-    for line in contents?.lines() {
-        if line.starts_with("[") {
-            header_title = line.get(1..line.len() - 1).unwrap();
-            if res.get(header_title).is_none() {
-                res.insert(header_title.to_string(), HashMap::new());
+        let mut icons: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+        for (idx, dir) in directories.iter().enumerate() {
+            for base_dir in base_dirs {
+                let Ok(files) = fs::read_dir(format!("{}/{}/{}", base_dir, name, dir.path)) else {
+                    continue;
+                };
+                for file in files.flatten() {
+                    let path = file.path();
+                    let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+                        continue;
+                    };
+                    if !ICON_EXTENSIONS.iter().any(|e| ext == *e) {
+                        continue;
+                    }
+                    icons
+                        .entry(stem.to_string_lossy().to_string())
+                        .or_default()
+                        .push((idx, path.to_string_lossy().to_string()));
+                }
             }
-        } else {
-            let split = line.split_once("=");
-            if let Some((k, v)) = split {
-                res.get_mut(header_title)
-                    .unwrap()
-                    .insert(k.to_string(), v.to_string());
-            };
         }
+
+        // Same directory, prefer png over svg over xpm
+        for candidates in icons.values_mut() {
+            candidates.sort_by_key(|(idx, path)| {
+                let ext_rank = ICON_EXTENSIONS
+                    .iter()
+                    .position(|ext| path.ends_with(ext))
+                    .unwrap_or(ICON_EXTENSIONS.len());
+                (*idx, ext_rank)
+            });
+        }
+
+        Some(IconTheme {
+            name: name.to_string(),
+            parents,
+            directories,
+            icons,
+        })
     }
 
-    Ok(res)
+    // Exact size match if there is one, otherwise the closest size this theme has
+    pub fn lookup(&self, name: &str, size: u32) -> Option<&String> {
+        let candidates = self.icons.get(name)?;
+
+        if let Some((_, path)) = candidates
+            .iter()
+            .find(|(idx, _)| self.directories[*idx].matches_size(size))
+        {
+            return Some(path);
+        }
+
+        candidates
+            .iter()
+            .min_by_key(|(idx, _)| self.directories[*idx].size_distance(size))
+            .map(|(_, path)| path)
+    }
+}
+
+pub struct IconFinder {
+    themes: HashMap<String, IconTheme>,
+    // Themes to search in order, each followed by its Inherits chain
+    theme_order: Vec<String>,
+    // Icons found directly in the base dirs or in /usr/share/pixmaps, no size information
+    fallback: HashMap<String, String>,
+}
+
+impl Default for IconFinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn icon_base_dirs() -> Vec<String> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let data_home =
+        std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!("{}/.local/share", home));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share".to_string());
+
+    std::iter::once(format!("{}/.icons", home))
+        .chain(std::iter::once(format!("{}/icons", data_home)))
+        .chain(data_dirs.split(":").map(|dir| format!("{}/icons", dir)))
+        .collect()
 }
 
 fn get_gtk_settings_theme() -> Option<String> {
     let home = std::env::var("HOME").unwrap();
-    match parse_ini_file(format!("{}/.config/gtk-3.0/settings.ini", home)) {
-        Ok(i) => i
-            .get("Settings")
-            .unwrap()
-            .get("gtk-icon-theme-name")
-            .map(|theme| theme.to_string()),
-        Err(_) => match parse_ini_file(format!("{}/.config/gtk-4.0/settings.ini", home)) {
-            Ok(i) => i
-                .get("Settings")
-                .unwrap()
-                .get("gtk-icon-theme-name")
-                .map(|theme| theme.to_string()),
-            Err(_) => None,
-        },
-    }
-}
-fn generate_map() -> (HashMap<IconConfig, String>, HashSet<u32>) {
+    ["gtk-3.0", "gtk-4.0"].iter().find_map(|version| {
+        parse_ini_file(format!("{}/.config/{}/settings.ini", home, version))
+            .ok()?
+            .group("Settings")?
+            .get_string("gtk-icon-theme-name")
+    })
+}
+
+fn get_kde_theme() -> Option<String> {
     let home = std::env::var("HOME").unwrap();
-    let mut map: HashMap<IconConfig, String> = HashMap::new();
-    let mut sizes: HashSet<u32> = HashSet::new();
-
-    let env_folders = std::env::var("XDG_DATA_DIRS")
-        .unwrap_or(format!("/usr/share:{}/.local/share", home).to_string());
-
-    let base_folders = env_folders.split(":");
-
-    let mut themes = vec!["hicolor".to_string()];
-
-    if let Some(theme_name) = get_gtk_settings_theme() {
-        themes.push(theme_name);
-    }
-    for theme in themes {
-        // Try to find and parse the index.theme file for the theme being processed
-        for base_folder in base_folders.clone() {
-            let path = format!("{}/icons/{}/index.theme", base_folder, theme);
-
-            let ini: IniMap = match parse_ini_file(path.clone()) {
-                Ok(i) => i,
-                Err(_) => continue,
-            };
-
-            let dirs: Vec<String> = ini
-                .get("Icon Theme")
-                .unwrap()
-                .get("Directories")
-                .unwrap()
-                .split(",")
-                .map(|x| x.to_string())
-                .filter(|x| !x.is_empty())
-                .collect();
-
-            // Traverse the base_folders again to include all the icons that may exist for this theme
-            for base_folder in base_folders.clone() {
-                for dir in dirs.iter() {
-                    if ini.get(dir).is_none() {
-                        debug!("Section {} not found", dir);
-                        continue;
-                    }
-                    let section = ini.get(dir).unwrap();
-                    let size: u32 = section.get("Size").unwrap().parse().unwrap();
-                    let scale = section.get("Scale").map_or("1", |v| v);
+    let contents = fs::read_to_string(format!("{}/.config/kdeglobals", home)).ok()?;
+    parse_ini_lenient(&contents)
+        .group("Icons")?
+        .get_string("Theme")
+}
 
-                    if scale.parse::<u32>().unwrap() > 1 {
-                        //FIXME(quadrado): For now ignore scaled icons
-                        continue;
-                    }
-                    sizes.insert(size);
-
-                    let d = format!("{}/icons/{}/{}", base_folder, theme, dir);
-                    if let Ok(files) = fs::read_dir(d) {
-                        for file in files {
-                            let fpath =
-                                file.unwrap().path().into_os_string().into_string().unwrap();
-
-                            let fname_no_ext = std::path::Path::new(&fpath)
-                                .file_stem()
-                                .unwrap()
-                                .to_os_string()
-                                .to_str()
-                                .unwrap()
-                                .to_string();
-
-                            //fpath.split("/").last().unwrap().split(".").next().unwrap();
-
-                            // println!("{} {}", fname_no_ext, fpath);
-                            let icon_config = IconConfig {
-                                size,
-                                name: fname_no_ext,
-                            };
-                            map.insert(icon_config, fpath);
-                        }
-                    }
+// GNOME keeps its settings in dconf, ask gsettings
+fn get_gnome_theme() -> Option<String> {
+    let output = std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+        .output()
+        .ok()?;
+    let theme = String::from_utf8(output.stdout).ok()?;
+    let theme = theme.trim().trim_matches('\'');
+
+    (!theme.is_empty()).then(|| theme.to_string())
+}
+
+// Configured themes first, then the desktop's default theme and hicolor as the last resort
+fn preferred_themes() -> Vec<String> {
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    let is_kde = desktop.split(":").any(|d| d == "KDE");
+
+    let mut themes = match is_kde {
+        true => vec![get_kde_theme(), get_gtk_settings_theme()],
+        false => vec![get_gtk_settings_theme(), get_gnome_theme(), get_kde_theme()],
+    }
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>();
+
+    themes.push(match is_kde {
+        true => "breeze".to_string(),
+        false => "Adwaita".to_string(),
+    });
+    themes.push("hicolor".to_string());
+    themes.dedup();
+    themes
+}
+
+fn fallback_icons(base_dirs: &[String]) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+
+    for dir in base_dirs
+        .iter()
+        .map(|d| d.as_str())
+        .chain(std::iter::once("/usr/share/pixmaps"))
+    {
+        let Ok(files) = fs::read_dir(dir) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            if path.is_dir() {
+                continue;
+            }
+            if let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) {
+                if ICON_EXTENSIONS.iter().any(|e| ext == *e) {
+                    map.entry(stem.to_string_lossy().to_string())
+                        .or_insert(path.to_string_lossy().to_string());
                 }
             }
-            // index.theme found and processed, can exit now
-            break;
         }
     }
+    map
+}
 
-    // Process /usr/share/pixmaps
-    if let Ok(files) = fs::read_dir("/usr/share/pixmaps/") {
-        for file in files {
-            if file.as_ref().unwrap().file_type().unwrap().is_dir() {
+impl IconFinder {
+    pub fn new() -> IconFinder {
+        let base_dirs = icon_base_dirs();
+        let theme_order = preferred_themes();
+        let mut themes = HashMap::new();
+
+        // Load the preferred themes and everything they inherit from
+        let mut pending = theme_order.clone();
+        while let Some(name) = pending.pop() {
+            if themes.contains_key(&name) {
                 continue;
             }
-            let fpath = file.unwrap().path().into_os_string().into_string().unwrap();
-            let fname_no_ext = fpath.split("/").last().unwrap().split(".").next().unwrap();
+            match IconTheme::load(&name, &base_dirs) {
+                Some(theme) => {
+                    pending.extend(theme.parents.iter().cloned());
+                    themes.insert(name, theme);
+                }
+                None => debug!("Icon theme {} not found", name),
+            }
+        }
 
-            let icon_config = IconConfig {
-                size: 32, //FIXME(quadrado): Don't use this fixed size that means nothing. Need
-                //to open the image and calculate the proper size.
-                name: fname_no_ext.to_string(),
-            };
-            map.insert(icon_config, fpath);
+        IconFinder {
+            themes,
+            theme_order,
+            fallback: fallback_icons(&base_dirs),
         }
     }
 
-    (map, sizes)
+    pub fn get_icon(&self, name: String) -> Option<String> {
+        self.get_icon_with_size(name, 32)
+    }
+
+    pub fn get_icon_with_size(&self, name: String, size: u32) -> Option<String> {
+        // First check if icon identifier is a path
+        if name.starts_with("/") {
+            return fs::metadata(&name).is_ok().then_some(name);
+        }
+
+        let mut visited = HashSet::new();
+        self.theme_order
+            .iter()
+            .find_map(|theme| self.find_in_theme(theme, &name, size, &mut visited))
+            .or_else(|| self.fallback.get(&name))
+            .cloned()
+    }
+
+    // Search a theme then its parents, depth first as the spec describes
+    fn find_in_theme<'a>(
+        &'a self,
+        theme: &str,
+        name: &str,
+        size: u32,
+        visited: &mut HashSet<String>,
+    ) -> Option<&'a String> {
+        if !visited.insert(theme.to_string()) {
+            return None;
+        }
+        let theme = self.themes.get(theme)?;
+
+        theme.lookup(name, size).or_else(|| {
+            theme
+                .parents
+                .iter()
+                .find_map(|parent| self.find_in_theme(parent, name, size, visited))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ini() {
+        let ini = parse_ini(
+            "# comment\n\n[Desktop Entry]\nName = Files \nName[pt]=Ficheiros\nComment=A\\sfile\\tmanager\nHidden=true\nKeywords=folder;dir\\;ectory;;\n[Desktop Action new]\nExec=nautilus --new-window\n",
+        )
+        .unwrap();
+
+        let entry = ini.group("Desktop Entry").unwrap();
+        assert_eq!(entry.get_string("Name").unwrap(), "Files");
+        assert_eq!(entry.get_string("Comment").unwrap(), "A file\tmanager");
+        assert_eq!(entry.get_boolean("Hidden"), Some(true));
+        assert_eq!(entry.get_boolean("NoDisplay"), None);
+        assert_eq!(entry.get_list("Keywords"), vec!["folder", "dir;ectory"]);
+        assert_eq!(
+            entry
+                .get_localestring("Name", &locale_variants("pt_PT.UTF-8"))
+                .unwrap(),
+            "Ficheiros"
+        );
+        assert_eq!(
+            entry
+                .get_localestring("Name", &locale_variants("en_US"))
+                .unwrap(),
+            "Files"
+        );
+        assert!(ini.group("Desktop Action new").is_some());
+    }
+
+    #[test]
+    fn test_parse_ini_errors() {
+        assert_eq!(
+            parse_ini("Name=foo\n").unwrap_err(),
+            IniError::Parse {
+                line: 1,
+                message: "Key found before any group header".to_string()
+            }
+        );
+        assert!(matches!(
+            parse_ini("[Group]\n\nnot a pair\n"),
+            Err(IniError::Parse { line: 3, .. })
+        ));
+        assert!(matches!(
+            parse_ini("[Group\n"),
+            Err(IniError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_ini_lenient() {
+        let ini = parse_ini_lenient(
+            "[General]\nColorScheme=BreezeDark\n[Colors:Header][Inactive]\nBackgroundNormal=1,2,3\nstray line\n[Icons]\nTheme=Papirus-Dark\n",
+        );
+        assert_eq!(
+            ini.group("Icons").unwrap().get_string("Theme").unwrap(),
+            "Papirus-Dark"
+        );
+        // Keys of the skipped group don't end up in the one before it
+        assert_eq!(ini.group("General").unwrap().values.len(), 1);
+        assert!(parse_ini("[Colors:Header][Inactive]\n").is_err());
+    }
+
+    #[test]
+    fn test_locale_variants() {
+        assert_eq!(
+            locale_variants("pt_BR.UTF-8@euro"),
+            vec!["pt_BR@euro", "pt_BR", "pt@euro", "pt"]
+        );
+        assert_eq!(locale_variants("de_DE.UTF-8"), vec!["de_DE", "de"]);
+        assert!(locale_variants("C").is_empty());
+    }
+
+    fn directory(path: &str, size: u32, kind: DirectoryType) -> IconDirectory {
+        IconDirectory {
+            path: path.to_string(),
+            size,
+            scale: 1,
+            min_size: 8,
+            max_size: 512,
+            threshold: 2,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_directory_size() {
+        let fixed = directory("48x48/apps", 48, DirectoryType::Fixed);
+        assert!(fixed.matches_size(48));
+        assert_eq!(fixed.size_distance(32), 16);
+
+        let threshold = directory("32x32/apps", 32, DirectoryType::Threshold);
+        assert!(threshold.matches_size(34));
+        assert!(!threshold.matches_size(35));
+        assert_eq!(threshold.size_distance(24), 6);
+
+        let scalable = directory("scalable/apps", 48, DirectoryType::Scalable);
+        assert!(scalable.matches_size(256));
+        assert_eq!(scalable.size_distance(1024), 512);
+
+        // 48 pixels, but not a 48 size directory
+        let scaled = IconDirectory {
+            scale: 2,
+            ..directory("24x24@2/apps", 24, DirectoryType::Fixed)
+        };
+        assert!(!scaled.matches_size(48));
+        assert_eq!(scaled.size_distance(48), 0);
+    }
+
+    #[test]
+    fn test_theme_lookup_closest() {
+        let theme = IconTheme {
+            name: "test".to_string(),
+            directories: vec![
+                directory("16x16/apps", 16, DirectoryType::Fixed),
+                directory("256x256/apps", 256, DirectoryType::Fixed),
+                directory("48x48/apps", 48, DirectoryType::Fixed),
+            ],
+            icons: HashMap::from([(
+                "firefox".to_string(),
+                vec![
+                    (0, "16.png".to_string()),
+                    (1, "256.png".to_string()),
+                    (2, "48.png".to_string()),
+                ],
+            )]),
+            ..Default::default()
+        };
+
+        assert_eq!(theme.lookup("firefox", 16).unwrap(), "16.png");
+        // Closest, not largest
+        assert_eq!(theme.lookup("firefox", 40).unwrap(), "48.png");
+        assert_eq!(theme.lookup("firefox", 200).unwrap(), "256.png");
+        assert!(theme.lookup("chromium", 32).is_none());
+    }
 }